@@ -1,13 +1,29 @@
 
 use crate::field::Field;
-use crate::make_distance_map_dog;
-use crate::make_distance_map_sheep;
+use crate::field::FieldConfig;
+use crate::map_file::format_field;
+use crate::map_file::parse_field;
+use crate::mdp::resume_value_iteration;
+use crate::mdp::ConvergenceReport;
+use crate::mdp::Mdp;
+use crate::mdp::Opponent;
+use crate::mdp::SolverOptions;
+use crate::policy_table::PolicyTable;
+use crate::sheep_policy::SheepPolicy;
 use crate::solve_markov;
+use crate::solve_markov::HerdingGame;
+use crate::terrain::state_index;
+use crate::terrain::Terrain;
+use crate::utility_table::UtilityTable;
 
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 
+use std::fs;
 use std::fs::File;
 
+use std::io::Error;
+use std::io::ErrorKind;
 use std::io::Read;
 use std::io::Result;
 
@@ -15,11 +31,19 @@ use std::collections::HashMap;
 
 use std::io::Write;
 use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 use crate::field::Dog;
 
 use crate::field::Sheep;
 
+pub(crate) type PartitionedData = (
+    Vec<((Sheep, Dog), f32)>,
+    Vec<((Sheep, Dog), f32)>,
+    Vec<((Sheep, Dog), f32)>,
+);
+
 pub(crate) fn to_key(key: (Sheep, Dog)) -> String {
     serde_json::to_string(&key).unwrap_or("".to_string())
 }
@@ -28,76 +52,305 @@ pub(crate) fn from_serialized(json: String) -> (Sheep, Dog) {
     serde_json::from_str(&json).unwrap_or((Sheep::new(), Dog::new()))
 }
 
+// what a utility map was solved for, kept with it so a solve for a different field, sheep, discount
+// or opponent isn't read back as this one
+#[derive(PartialEq, Serialize, Deserialize)]
+struct Solved {
+    config: FieldConfig,
+    sheep_policy: String,
+    discount: f32,
+    opponent: Opponent,
+}
+
+impl Solved {
+    fn new(config: &FieldConfig, policy: &dyn SheepPolicy, options: &SolverOptions) -> Self {
+        Self {
+            config: config.clone(),
+            sheep_policy: policy.name(),
+            discount: options.discount,
+            opponent: options.opponent,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedMap {
+    solved: Solved,
+    utilities: HashMap<String, f32>,
+}
+
+fn to_utilities(table: &UtilityTable) -> HashMap<String, f32> {
+    table.iter().map(|(key, value)| (to_key(key), value)).collect()
+}
+
+// None when a key isn't a state of the field
+fn from_utilities(config: &FieldConfig, utilities: &HashMap<String, f32>) -> Option<UtilityTable> {
+    let mut table = UtilityTable::new(config.size);
+    for (key, value) in utilities {
+        let state = serde_json::from_str::<(Sheep, Dog)>(key).ok()?;
+        state_index(config.size, state)?;
+        table.set(state, *value);
+    }
+    Some(table)
+}
+
 pub(crate) fn save_utility_map<P: AsRef<Path>>(
     path: P,
-    map: HashMap<(Sheep, Dog), f32>,
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    options: &SolverOptions,
+    table: &UtilityTable,
 ) -> Result<()> {
-    let mut new_map = HashMap::new();
-    for (key, value) in &map {
-        new_map.insert(to_key(*key), value);
-    }
+    let saved = SavedMap {
+        solved: Solved::new(config, policy, options),
+        utilities: to_utilities(table),
+    };
     let mut f = File::create(path)?;
-    println!("created the file");
-    match serde_json::to_vec(&map) {
-        Ok(_) => (),
-        Err(e) => println!("{}", e),
-    }
-    let buf = serde_json::to_vec(&new_map)?;
-    println!("serialized the data structure");
+    let buf = serde_json::to_vec(&saved)?;
     f.write_all(&buf[..])?;
-    println!("wrote to the file");
     Ok(())
 }
 
-pub(crate) fn inner_load_utility_map<P: AsRef<Path>>(path: P) -> Option<HashMap<String, f32>> {
-    if let Ok(mut file) = File::open(path) {
-        let mut buf = vec![];
-        if file.read_to_end(&mut buf).is_ok() {
-            if let Ok(map) = serde_json::from_slice(&buf[..]) {
-                return Some(map);
+// None when there is no saved map, or it was solved for something else or misses a state of the
+// game
+pub(crate) fn inner_load_utility_map<P: AsRef<Path>>(
+    path: P,
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    options: &SolverOptions,
+) -> Option<UtilityTable> {
+    let mut file = File::open(path).ok()?;
+    let mut buf = vec![];
+    file.read_to_end(&mut buf).ok()?;
+    let saved: SavedMap = serde_json::from_slice(&buf[..]).ok()?;
+    if saved.solved != Solved::new(config, policy, options) {
+        println!("the saved map was solved for something else, solving again");
+        return None;
+    }
+    let table = from_utilities(config, &saved.utilities)?;
+    let game = HerdingGame::new(config, policy);
+    if !game.states().iter().all(|state| table.contains(*state)) {
+        println!("the saved map doesn't cover every state, solving again");
+        return None;
+    }
+    Some(table)
+}
+
+// a utility table part way through value iteration, with how far the run had got and what it was
+// solving, so a run solving something else doesn't pick it up
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    solved: Solved,
+    report: ConvergenceReport,
+    utilities: HashMap<String, f32>,
+}
+
+// where the checkpoints of the solve for a utility map go: next to it, with .checkpoint added
+pub(crate) fn checkpoint_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut name = path.as_ref().as_os_str().to_owned();
+    name.push(".checkpoint");
+    PathBuf::from(name)
+}
+
+// written to a temporary file first and then moved over the old checkpoint, so being interrupted
+// halfway through a write never loses the last good one
+pub(crate) fn save_checkpoint<P: AsRef<Path>>(
+    path: P,
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    options: &SolverOptions,
+    table: &UtilityTable,
+    report: &ConvergenceReport,
+) -> Result<()> {
+    let checkpoint = Checkpoint {
+        solved: Solved::new(config, policy, options),
+        report: report.clone(),
+        utilities: to_utilities(table),
+    };
+    let mut temporary = path.as_ref().as_os_str().to_owned();
+    temporary.push(".tmp");
+    let mut f = File::create(&temporary)?;
+    let buf = serde_json::to_vec(&checkpoint)?;
+    f.write_all(&buf[..])?;
+    f.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+// None when there is no checkpoint, or it was solving something else
+pub(crate) fn load_checkpoint<P: AsRef<Path>>(
+    path: P,
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    options: &SolverOptions,
+) -> Option<(UtilityTable, ConvergenceReport)> {
+    let mut file = File::open(path).ok()?;
+    let mut buf = vec![];
+    file.read_to_end(&mut buf).ok()?;
+    let checkpoint: Checkpoint = serde_json::from_slice(&buf[..]).ok()?;
+    if checkpoint.solved != Solved::new(config, policy, options) {
+        println!("the checkpoint was solving something else, starting over");
+        return None;
+    }
+    let table = from_utilities(config, &checkpoint.utilities)?;
+    Some((table, checkpoint.report))
+}
+
+// solve for the utility map kept at path, resuming from the checkpoint next to it if there is one
+// and checkpointing as it goes. once the solve converges the map is saved, and only after that
+// worked is the checkpoint removed
+pub(crate) fn solve_with_checkpoints<P: AsRef<Path>>(
+    path: P,
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    options: &SolverOptions,
+) -> (UtilityTable, ConvergenceReport) {
+    let checkpoint_path = checkpoint_path(&path);
+    let game = HerdingGame::new(config, policy);
+    let mut resume_from = load_checkpoint(&checkpoint_path, config, policy, options);
+    if let Some((table, report)) = &resume_from {
+        if game.states().iter().all(|state| table.contains(*state)) {
+            println!(
+                "resuming from the checkpoint at iteration {}, residual {}",
+                report.iterations, report.residual
+            );
+        } else {
+            println!("the checkpoint doesn't cover every state, starting over");
+            resume_from = None;
+        }
+    }
+    let (table, report) = resume_value_iteration(&game, options, resume_from, |table, report| {
+        if let Err(e) = save_checkpoint(&checkpoint_path, config, policy, options, table, report) {
+            println!("could not checkpoint: {}", e);
+        }
+    });
+    if report.converged {
+        match save_utility_map(&path, config, policy, options, &table) {
+            Ok(()) => {
+                let _ = fs::remove_file(&checkpoint_path);
             }
+            Err(e) => println!("could not save the map, keeping the checkpoint: {}", e),
         }
     }
-    None
+    (table, report)
 }
 
-pub(crate) fn load_utility_map<P: AsRef<Path>>(path: P) -> HashMap<(Sheep, Dog), f32> {
-    match inner_load_utility_map(path) {
-        Some(map) => {
-            let mut new_map = HashMap::new();
-            for (key, value) in &map {
-                new_map.insert(from_serialized(key.clone()), *value);
+pub(crate) fn load_utility_map<P: AsRef<Path>>(
+    path: P,
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    options: &SolverOptions,
+) -> UtilityTable {
+    match inner_load_utility_map(&path, config, policy, options) {
+        Some(table) => table,
+        None => {
+            let (table, report) = solve_with_checkpoints(path, config, policy, options);
+            if report.converged {
+                println!("solved the map: {:?}", report);
+            } else {
+                println!(
+                    "the map did not converge in {} iterations, it may be off by {}: {:?}",
+                    report.iterations, report.error_bound, report
+                );
             }
-            return new_map;
+            table
+        }
+    }
+}
+
+// a policy table with the field it was pulled out for, so a table for another field isn't read
+// back as this one's
+#[derive(Serialize, Deserialize)]
+struct SavedPolicy {
+    config: FieldConfig,
+    moves: HashMap<String, Dog>,
+}
+
+pub(crate) fn save_policy_table<P: AsRef<Path>>(
+    path: P,
+    config: &FieldConfig,
+    table: &PolicyTable,
+) -> Result<()> {
+    let mut moves = HashMap::new();
+    for (key, dog) in table.iter() {
+        moves.insert(to_key(key), dog);
+    }
+    let saved = SavedPolicy {
+        config: config.clone(),
+        moves,
+    };
+    let mut f = File::create(path)?;
+    let buf = serde_json::to_vec(&saved)?;
+    f.write_all(&buf[..])?;
+    Ok(())
+}
+
+// None when there is no saved table, or it was saved for a different field
+pub(crate) fn inner_load_policy_table<P: AsRef<Path>>(
+    path: P,
+    config: &FieldConfig,
+) -> Option<PolicyTable> {
+    let mut file = File::open(path).ok()?;
+    let mut buf = vec![];
+    file.read_to_end(&mut buf).ok()?;
+    let saved: SavedPolicy = serde_json::from_slice(&buf[..]).ok()?;
+    if saved.config != *config {
+        println!("the saved policy table is for a different field, pulling it out again");
+        return None;
+    }
+    let mut table = PolicyTable::new(config.size);
+    for (key, dog) in saved.moves {
+        let state = from_serialized(key);
+        state_index(config.size, state)?;
+        table.set(state, dog);
+    }
+    Some(table)
+}
+
+// read the dog's policy back, or pull it out of the utility map and save it when there is no
+// saved copy for this field
+pub(crate) fn load_policy_table<P: AsRef<Path>>(
+    path: P,
+    map: &UtilityTable,
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    options: &SolverOptions,
+) -> PolicyTable {
+    match inner_load_policy_table(&path, config) {
+        Some(table) => table,
+        None => {
+            let table = solve_markov::generate_dog_policy(config, policy, map, options);
+            if let Err(e) = save_policy_table(&path, config, &table) {
+                println!("could not save the policy table: {}", e);
+            }
+            table
         }
-        None => return solve_markov::generate_optimal_utlility(),
     }
 }
 
 pub(crate) fn partition_data(
-    map: &HashMap<(Sheep, Dog), f32>,
-) -> (
-    Vec<((Sheep, Dog), f32)>,
-    Vec<((Sheep, Dog), f32)>,
-    Vec<((Sheep, Dog), f32)>,
-) {
+    map: &UtilityTable,
+    config: &FieldConfig,
+    rng: &mut impl Rng,
+) -> PartitionedData {
     let mut training_data = Vec::new();
     let mut testing_data = Vec::new();
     let mut validation_data = Vec::new();
-    let mut rng = rand::thread_rng();
+    let terrain = Arc::new(Terrain::new(config));
 
-    for ((sheep, dog), value) in map {
-        let field = Field::with(*sheep, *dog);
+    // the table goes through the states in a fixed order, so the same seed always splits them
+    // the same way
+    for ((sheep, dog), value) in map.iter() {
+        let field = Field::on(&terrain, vec![sheep], vec![dog]);
         if !field.is_valid() {
             continue;
         }
         let probability = rng.gen_range(0..100);
         if probability < 70 {
-            training_data.push(((*sheep, *dog), *value));
+            training_data.push(((sheep, dog), value));
         } else if probability < 85 {
-            testing_data.push(((*sheep, *dog), *value));
+            testing_data.push(((sheep, dog), value));
         } else {
-            validation_data.push(((*sheep, *dog), *value));
+            validation_data.push(((sheep, dog), value));
         }
     }
     (training_data, testing_data, validation_data)
@@ -105,11 +358,7 @@ pub(crate) fn partition_data(
 
 pub(crate) fn save_partitioned_data<P: AsRef<Path>>(
     path: P,
-    data: (
-        Vec<((Sheep, Dog), f32)>,
-        Vec<((Sheep, Dog), f32)>,
-        Vec<((Sheep, Dog), f32)>,
-    ),
+    data: PartitionedData,
 ) -> Result<()> {
     let mut f = File::create(path)?;
     println!("created the file");
@@ -126,12 +375,10 @@ pub(crate) fn save_partitioned_data<P: AsRef<Path>>(
 
 pub(crate) fn load_partitioned_data<P: AsRef<Path>>(
     path: P,
-    map: &HashMap<(Sheep, Dog), f32>,
-) -> (
-    Vec<((Sheep, Dog), f32)>,
-    Vec<((Sheep, Dog), f32)>,
-    Vec<((Sheep, Dog), f32)>,
-) {
+    map: &UtilityTable,
+    config: &FieldConfig,
+    rng: &mut impl Rng,
+) -> PartitionedData {
     if let Ok(mut file) = File::open(path) {
         let mut buf = vec![];
         if file.read_to_end(&mut buf).is_ok() {
@@ -140,42 +387,134 @@ pub(crate) fn load_partitioned_data<P: AsRef<Path>>(
             }
         }
     }
-    return partition_data(map);
+    partition_data(map, config, rng)
 }
 
-pub(crate) fn save_distance_data<P: AsRef<Path>>(
-    path: P,
-    data: (
-        HashMap<Sheep, f32>,
-        HashMap<(Sheep, Dog), f32>
-    ),
-) -> Result<()> {
+pub(crate) fn save_field<P: AsRef<Path>>(path: P, field: &Field) -> Result<()> {
     let mut f = File::create(path)?;
-    println!("created the file");
-    match serde_json::to_vec(&data) {
-        Ok(_) => (),
-        Err(e) => println!("{}", e),
-    }
-    let buf = serde_json::to_vec(&data)?;
-    println!("serialized the data structure");
-    f.write_all(&buf[..])?;
-    println!("wrote to the file");
+    f.write_all(format_field(field).as_bytes())?;
     Ok(())
 }
 
-pub(crate) fn load_distance_data<P: AsRef<Path>>(
-    path: P,
-) -> (
-    HashMap<Sheep, f32>,
-    HashMap<(Sheep, Dog), f32>
-) {
-    if let Ok(mut file) = File::open(path) {
-        let mut buf = vec![];
-        if file.read_to_end(&mut buf).is_ok() {
-            if let Ok(partitioned_data) = serde_json::from_slice(&buf[..]) {
-                return partitioned_data;
-            }
-        }
+pub(crate) fn load_field<P: AsRef<Path>>(path: P) -> Result<Field> {
+    let mut text = String::new();
+    File::open(path)?.read_to_string(&mut text)?;
+    parse_field(&text).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::move_set::MoveSet;
+    use crate::mdp::value_iteration;
+    use crate::sheep_policy::Flee;
+    use crate::sheep_policy::RandomWalk;
+
+    // a path in the temporary directory that no other test uses
+    fn scratch_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("project3_{}_{}", name, std::process::id()))
     }
-    return (make_distance_map_sheep(), make_distance_map_dog());
-}
\ No newline at end of file
+
+    #[test]
+    fn a_converged_solve_is_saved_and_its_checkpoint_removed() {
+        let path = scratch_path("converged_map");
+        let config = FieldConfig::new(7);
+        let policy = Flee::default();
+        let options = SolverOptions {
+            checkpoint_every: 5,
+            ..SolverOptions::default()
+        };
+        let solved = load_utility_map(&path, &config, &policy, &options);
+        assert!(path.exists());
+        assert!(!checkpoint_path(&path).exists());
+        let loaded = load_utility_map(&path, &config, &policy, &options);
+        fs::remove_file(&path).unwrap();
+        assert!(loaded == solved);
+    }
+
+    // a map saved for one solve is solved again rather than read back for any other, and a file
+    // that isn't a map at all is too
+    #[test]
+    fn a_saved_map_is_only_loaded_for_its_own_solve() {
+        let path = scratch_path("other_map");
+        let config = FieldConfig::new(7);
+        let policy = Flee::default();
+        let options = SolverOptions::default();
+        load_utility_map(&path, &config, &policy, &options);
+        assert!(inner_load_utility_map(&path, &config, &policy, &options).is_some());
+        assert!(inner_load_utility_map(&path, &config, &RandomWalk, &options).is_none());
+        let wider = Flee { view_radius: 3 };
+        assert!(inner_load_utility_map(&path, &config, &wider, &options).is_none());
+        let adversarial = SolverOptions {
+            opponent: Opponent::Adversarial,
+            ..options.clone()
+        };
+        assert!(inner_load_utility_map(&path, &config, &policy, &adversarial).is_none());
+
+        let larger = FieldConfig::new(9);
+        let map = load_utility_map(&path, &larger, &policy, &options);
+        assert_eq!(map.size(), 9);
+        assert!(map.contains((Sheep::at(8, 8), larger.dog_start)));
+
+        fs::write(&path, "not a map").unwrap();
+        let map = load_utility_map(&path, &config, &policy, &options);
+        assert_eq!(map.size(), 7);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_saved_field_loads_back() {
+        let path = scratch_path("field");
+        let field = Field::with(&FieldConfig::new(7), Sheep::at(1, 5), Dog::at(4, 0));
+        save_field(&path, &field).unwrap();
+        let loaded = load_field(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(format_field(&loaded), format_field(&field));
+    }
+
+    #[test]
+    fn a_saved_policy_table_loads_back_only_for_its_field() {
+        let path = scratch_path("policy_table");
+        let config = FieldConfig::new(7);
+        let policy = Flee::default();
+        let options = SolverOptions::default();
+        let (map, _) = value_iteration(&HerdingGame::new(&config, &policy), &options);
+        let table = load_policy_table(&path, &map, &config, &policy, &options);
+        assert!(table.iter().next().is_some());
+        assert!(inner_load_policy_table(&path, &config) == Some(table));
+        assert!(inner_load_policy_table(&path, &FieldConfig::new(9)).is_none());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_checkpoint_for_anything_else_is_rejected() {
+        let path = scratch_path("stale_checkpoint");
+        let config = FieldConfig::new(7);
+        let policy = Flee::default();
+        let options = SolverOptions {
+            max_iterations: 3,
+            checkpoint_every: 0,
+            ..SolverOptions::default()
+        };
+        let (table, report) = value_iteration(&HerdingGame::new(&config, &policy), &options);
+        save_checkpoint(&path, &config, &policy, &options, &table, &report).unwrap();
+
+        assert!(load_checkpoint(&path, &config, &policy, &options).is_some());
+        let other_discount = SolverOptions {
+            discount: 0.9,
+            ..options.clone()
+        };
+        assert!(load_checkpoint(&path, &config, &policy, &other_discount).is_none());
+        let mut other_field = config.clone();
+        other_field.dog_moves = MoveSet::von_neumann();
+        assert!(load_checkpoint(&path, &other_field, &policy, &options).is_none());
+        assert!(load_checkpoint(&path, &config, &RandomWalk, &options).is_none());
+        let adversarial = SolverOptions {
+            opponent: Opponent::Adversarial,
+            ..options.clone()
+        };
+        assert!(load_checkpoint(&path, &config, &policy, &adversarial).is_none());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,203 @@
+use std::sync::Arc;
+
+use crate::field::Cell;
+use crate::field::Field;
+use crate::field::FieldConfig;
+use crate::sheep_policy::SheepPolicy;
+use crate::terrain::state_index;
+use crate::terrain::Terrain;
+use crate::utility_table::UtilityTable;
+
+// the dog's best move for every state and every number of moves left before a deadline, when all
+// that counts is getting the sheep penned in time
+pub struct DeadlinePolicy {
+    size: i32,
+    // moves[k] is for when k + 1 moves are left: for every state, where its move comes in
+    // Field::get_dog_states, or NO_MOVE when the game is already over there
+    moves: Vec<Vec<u8>>,
+}
+
+const NO_MOVE: u8 = u8::MAX;
+
+impl DeadlinePolicy {
+    // the most moves left the policy has a move for
+    pub fn horizon(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn next_cell(&self, field: &Field, moves_left: usize) -> Cell {
+        assert!(
+            (1..=self.horizon()).contains(&moves_left),
+            "the policy covers 1 to {} moves left, not {}",
+            self.horizon(),
+            moves_left
+        );
+        let state = (field.sheep(), field.dog());
+        let action = state_index(self.size, state)
+            .map(|index| self.moves[moves_left - 1][index])
+            .filter(|action| *action != NO_MOVE)
+            .unwrap_or_else(|| panic!("the deadline policy has no move for {:?}", state));
+        field.get_dog_states()[action as usize].dog().as_cell()
+    }
+}
+
+// the highest chance of penning the sheep within horizon moves from every state, along with the
+// time indexed policy that gets it. backward induction from the deadline: with no moves left only
+// a penned sheep counts, and with k left the dog picks the move whose reaction leaves the best
+// chance with k - 1 left. ties go to the first move. the policy keeps a byte per state for every
+// move left. reached is handed the chances with each number of moves left as they are worked out,
+// so one solve to the longest deadline covers all the shorter ones
+pub(crate) fn solve_deadline(
+    config: &FieldConfig,
+    sheep_policy: &dyn SheepPolicy,
+    horizon: usize,
+    mut reached: impl FnMut(usize, &UtilityTable),
+) -> (UtilityTable, DeadlinePolicy) {
+    let terrain = Arc::new(Terrain::new(config));
+    let count = terrain.state_count();
+
+    // the chance with no moves left, and each playable state's moves with where they lead
+    let mut chance = vec![0.0f64; count];
+    let mut valid = vec![false; count];
+    let mut choices = vec![Vec::<Vec<(usize, f64)>>::new(); count];
+    for index in 0..count {
+        let (sheep, dog) = terrain.state(index);
+        let field = Field::on(&terrain, vec![sheep], vec![dog]);
+        if !field.is_valid() {
+            continue;
+        }
+        valid[index] = true;
+        if field.dog_won() {
+            chance[index] = 1.0;
+            continue;
+        }
+        if field.sheep_won() {
+            continue;
+        }
+        let dog_states = field.get_dog_states();
+        assert!(
+            dog_states.len() < NO_MOVE as usize,
+            "the dog has {} moves, too many to store",
+            dog_states.len()
+        );
+        for next in dog_states {
+            let outcomes = next
+                .sheep_transitions(0, sheep_policy)
+                .into_iter()
+                .map(|(outcome, probability)| {
+                    (
+                        terrain.index((outcome.sheep(), outcome.dog())),
+                        probability as f64,
+                    )
+                })
+                .collect();
+            choices[index].push(outcomes);
+        }
+    }
+
+    let to_table = |chance: &[f64]| {
+        let mut chances = UtilityTable::new(config.size);
+        for index in 0..count {
+            if valid[index] {
+                chances.set(terrain.state(index), chance[index] as f32);
+            }
+        }
+        chances
+    };
+
+    let mut chances = to_table(&chance);
+    reached(0, &chances);
+    let mut moves = Vec::with_capacity(horizon);
+    for moves_left in 1..=horizon {
+        let mut actions = vec![NO_MOVE; count];
+        let mut next_chance = chance.clone();
+        for (index, outcomes_by_action) in choices.iter().enumerate() {
+            let mut best: Option<(usize, f64)> = None;
+            for (action, outcomes) in outcomes_by_action.iter().enumerate() {
+                let value: f64 = outcomes
+                    .iter()
+                    .map(|(next, probability)| probability * chance[*next])
+                    .sum();
+                if best.is_none_or(|(_, best_value)| value > best_value) {
+                    best = Some((action, value));
+                }
+            }
+            if let Some((action, value)) = best {
+                actions[index] = action as u8;
+                next_chance[index] = value;
+            }
+        }
+        chance = next_chance;
+        moves.push(actions);
+        chances = to_table(&chance);
+        reached(moves_left, &chances);
+    }
+    (
+        chances,
+        DeadlinePolicy {
+            size: config.size,
+            moves,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::field::Sheep;
+    use crate::sheep_policy::Flee;
+    use crate::simulations::run_deadline_simulation;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn chances_only_grow_with_more_moves() {
+        let config = FieldConfig::new(7);
+        let policy = Flee::default();
+        let mut by_moves_left = Vec::new();
+        let (chances, deadline_policy) =
+            solve_deadline(&config, &policy, 12, |moves_left, chances| {
+                by_moves_left.push((moves_left, chances.clone()));
+            });
+        assert_eq!(deadline_policy.horizon(), 12);
+        assert_eq!(by_moves_left.len(), 13);
+        assert!(by_moves_left[12].1 == chances);
+        for (moves_left, pair) in by_moves_left.windows(2).enumerate() {
+            assert_eq!(pair[0].0, moves_left);
+            for (state, chance) in pair[0].1.iter() {
+                assert!(pair[1].1.get(state).unwrap() >= chance);
+            }
+        }
+    }
+
+    // games start from a random sheep cell with the dog at its start, the dog's own cell left out,
+    // so on average they should pen the sheep as often as the solved chances from those starts say
+    #[test]
+    fn simulated_games_match_the_solved_chance() {
+        let config = FieldConfig::new(7);
+        let policy = Flee::default();
+        let (chances, deadline_policy) = solve_deadline(&config, &policy, 10, |_, _| {});
+        let starts: Vec<f32> = (0..config.size)
+            .flat_map(|y| (0..config.size).map(move |x| Sheep::at(x, y)))
+            .filter(|sheep| (sheep.x, sheep.y) != (config.dog_start.x, config.dog_start.y))
+            .filter_map(|sheep| chances.get((sheep, config.dog_start)))
+            .collect();
+        let expected =
+            starts.iter().map(|chance| *chance as f64).sum::<f64>() / starts.len() as f64;
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let games = 20000;
+        let penned = (0..games)
+            .filter(|_| run_deadline_simulation(&deadline_policy, &config, &policy, &mut rng))
+            .count();
+        let simulated = penned as f64 / games as f64;
+        assert!(
+            (simulated - expected).abs() < 0.02,
+            "simulated {} against solved {}",
+            simulated,
+            expected
+        );
+    }
+}
@@ -0,0 +1,550 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::field::Cell;
+use crate::field::Dog;
+use crate::field::Field;
+use crate::field::FieldConfig;
+use crate::field::Sheep;
+use crate::math::dot_product;
+use crate::math::model_2;
+use crate::mdp::ConvergenceReport;
+use crate::policy_table::PolicyTable;
+use crate::sheep_policy::SheepPolicy;
+use crate::solve_markov::end_of_game;
+use crate::terrain::Terrain;
+use crate::utility_table::UtilityTable;
+
+// a deterministic way for the dog to play: the cell it moves to on a field with one sheep and one
+// dog. the cell has to be one of the field's dog moves
+pub trait DogPolicy: Sync {
+    fn next_cell(&self, field: &Field) -> Cell;
+}
+
+// the moves a solved table stores
+impl DogPolicy for PolicyTable {
+    fn next_cell(&self, field: &Field) -> Cell {
+        let state = (field.sheep(), field.dog());
+        self.get(state)
+            .unwrap_or_else(|| panic!("the policy table has no move for {:?}", state))
+            .as_cell()
+    }
+}
+
+// the cheapest move among the outcomes a score puts on them, weighted by how likely the sheep's
+// reaction to each is. ties go to the first move
+fn cheapest_move(
+    field: &Field,
+    sheep_policy: &dyn SheepPolicy,
+    score: impl Fn(&Field) -> f32,
+) -> Cell {
+    let mut best = field.dog().as_cell();
+    let mut best_value = f32::MAX;
+    for next in field.get_dog_states() {
+        let value: f32 = next
+            .sheep_transitions(0, sheep_policy)
+            .iter()
+            .map(|(outcome, probability)| probability * score(outcome))
+            .sum();
+        if value < best_value {
+            best = next.dog().as_cell();
+            best_value = value;
+        }
+    }
+    best
+}
+
+// the move the learned linear model expects to leave the least work, like
+// run_simulation_with_model but weighing every reaction instead of sampling one
+pub struct ModelGreedy<'a> {
+    pub model: (f32, f32, f32, f32, f32),
+    pub distances: HashMap<Sheep, f32>,
+    pub sheep_policy: &'a dyn SheepPolicy,
+}
+
+impl DogPolicy for ModelGreedy<'_> {
+    fn next_cell(&self, field: &Field) -> Cell {
+        cheapest_move(field, self.sheep_policy, |outcome| {
+            let data_vector = model_2(
+                (outcome.sheep(), outcome.dog()),
+                &self.distances,
+                outcome.config(),
+            );
+            dot_product(data_vector, self.model)
+        })
+    }
+}
+
+// a rule of thumb with no solve behind it: the move whose reaction is expected to leave the sheep
+// closest to the pen, never stepping where the sheep would run into the dog
+pub struct Lure<'a> {
+    pub sheep_policy: &'a dyn SheepPolicy,
+}
+
+impl DogPolicy for Lure<'_> {
+    fn next_cell(&self, field: &Field) -> Cell {
+        cheapest_move(field, self.sheep_policy, |outcome| {
+            end_of_game(outcome).unwrap_or_else(|| {
+                let sheep = outcome.sheep();
+                let goal = outcome.config().pen.goal;
+                outcome
+                    .config()
+                    .sheep_moves
+                    .distance(sheep.x - goal.0, sheep.y - goal.1) as f32
+                    + 1.0
+            })
+        })
+    }
+}
+
+// the Markov chain a dog policy turns the game into, over Terrain::index
+struct PolicyChain {
+    terrain: Arc<Terrain>,
+    valid: Vec<bool>,
+    penned: Vec<bool>,
+    // the sheep ran into the dog
+    lost: Vec<bool>,
+    // where each state that is still being played goes next, with the chances
+    successors: Vec<Vec<(usize, f64)>>,
+}
+
+fn policy_chain(
+    config: &FieldConfig,
+    sheep_policy: &dyn SheepPolicy,
+    dog_policy: &dyn DogPolicy,
+) -> PolicyChain {
+    let terrain = Arc::new(Terrain::new(config));
+    let count = terrain.state_count();
+    let mut chain = PolicyChain {
+        terrain: Arc::clone(&terrain),
+        valid: vec![false; count],
+        penned: vec![false; count],
+        lost: vec![false; count],
+        successors: vec![Vec::new(); count],
+    };
+    for index in 0..count {
+        let (sheep, dog) = terrain.state(index);
+        let field = Field::on(&terrain, vec![sheep], vec![dog]);
+        if !field.is_valid() {
+            continue;
+        }
+        chain.valid[index] = true;
+        if field.dog_won() {
+            chain.penned[index] = true;
+            continue;
+        }
+        if field.sheep_won() {
+            chain.lost[index] = true;
+            continue;
+        }
+        let cell = dog_policy.next_cell(&field);
+        let next = field.move_dog_to(0, cell);
+        assert!(
+            field
+                .get_dog_states()
+                .iter()
+                .any(|legal| legal.dog() == next.dog()),
+            "the dog can't move from ({}, {}) to ({}, {})",
+            dog.x,
+            dog.y,
+            cell.x,
+            cell.y
+        );
+        for (outcome, probability) in next.sheep_transitions(0, sheep_policy) {
+            chain.successors[index].push((
+                terrain.index((outcome.sheep(), outcome.dog())),
+                probability as f64,
+            ));
+        }
+    }
+    chain
+}
+
+// how exact the expected moves have to be before evaluate_dog_policy stops sweeping, and how many
+// sweeps it gets to try
+const EVALUATION_TOLERANCE: f64 = 1e-9;
+const EVALUATION_MAX_SWEEPS: usize = 100_000;
+
+// the expected number of moves until the sheep is penned when the dog follows dog_policy, for
+// every state, with how close the sweeps got. no discount is applied. states the game can go wrong
+// from, where the sheep runs into the dog or never gets penned with some chance, expect infinitely
+// many moves
+pub(crate) fn evaluate_dog_policy(
+    config: &FieldConfig,
+    sheep_policy: &dyn SheepPolicy,
+    dog_policy: &dyn DogPolicy,
+) -> (UtilityTable, ConvergenceReport) {
+    let chain = policy_chain(config, sheep_policy, dog_policy);
+    let (moves, report) = expected_moves(&chain.valid, &chain.penned, &chain.successors);
+    let mut table = UtilityTable::new(config.size);
+    for (index, expected) in moves.iter().enumerate() {
+        if chain.valid[index] {
+            table.set(chain.terrain.state(index), *expected as f32);
+        }
+    }
+    (table, report)
+}
+
+// solves T = 1 + P T over the states sure to end up penned, swept Gauss-Seidel style since the
+// matrix is far too large to factor. the sweeps start from nothing and only ever climb towards T,
+// so once r is the largest amount one more backup would still add, T - moves = (I - P)^-1 r is at
+// most r T, and every value is within r / (1 - r) of itself from the true one
+fn expected_moves(
+    valid: &[bool],
+    penned: &[bool],
+    successors: &[Vec<(usize, f64)>],
+) -> (Vec<f64>, ConvergenceReport) {
+    let start = Instant::now();
+    let count = valid.len();
+    let mut predecessors = vec![Vec::<usize>::new(); count];
+    for (index, nexts) in successors.iter().enumerate() {
+        for (next, _) in nexts {
+            predecessors[*next].push(index);
+        }
+    }
+    // the states with some chance of getting the sheep penned, and then the ones with some
+    // chance of getting stuck without
+    let can_pen = backward_closure(&predecessors, (0..count).filter(|index| penned[*index]));
+    let improper = backward_closure(
+        &predecessors,
+        (0..count).filter(|index| valid[*index] && !can_pen[*index]),
+    );
+    let proper: Vec<usize> = (0..count)
+        .filter(|index| valid[*index] && !penned[*index] && !improper[*index])
+        .collect();
+    let backup = |moves: &[f64], index: usize| {
+        1.0 + successors[index]
+            .iter()
+            .map(|(next, probability)| probability * moves[*next])
+            .sum::<f64>()
+    };
+
+    let mut moves = vec![0.0f64; count];
+    let mut report = ConvergenceReport::default();
+    while report.iterations < EVALUATION_MAX_SWEEPS {
+        let mut largest_change: f64 = 0.0;
+        for index in proper.iter() {
+            let value = backup(&moves, *index);
+            largest_change = largest_change.max(value - moves[*index]);
+            moves[*index] = value;
+        }
+        report.iterations += 1;
+        if largest_change < EVALUATION_TOLERANCE {
+            report.converged = true;
+            break;
+        }
+    }
+
+    let residual = proper
+        .iter()
+        .map(|index| backup(&moves, *index) - moves[*index])
+        .fold(0.0, f64::max);
+    let longest = proper.iter().map(|index| moves[*index]).fold(0.0, f64::max);
+    report.residual = residual as f32;
+    report.error_bound = if residual < 1.0 {
+        (longest * residual / (1.0 - residual)) as f32
+    } else {
+        f32::INFINITY
+    };
+    report.elapsed = start.elapsed();
+    for index in 0..count {
+        if improper[index] {
+            moves[index] = f64::INFINITY;
+        }
+    }
+    (moves, report)
+}
+
+// every state that can lead to one of the seeds, the seeds included
+fn backward_closure(predecessors: &[Vec<usize>], seeds: impl Iterator<Item = usize>) -> Vec<bool> {
+    let mut reached = vec![false; predecessors.len()];
+    let mut frontier: Vec<usize> = seeds.collect();
+    for index in frontier.iter() {
+        reached[*index] = true;
+    }
+    while let Some(index) = frontier.pop() {
+        for predecessor in predecessors[index].iter() {
+            if !reached[*predecessor] {
+                reached[*predecessor] = true;
+                frontier.push(*predecessor);
+            }
+        }
+    }
+    reached
+}
+
+// how long the dog following a policy takes to pen the sheep, as a distribution rather than an
+// expectation
+#[derive(Clone, Debug)]
+pub struct StepsToPen {
+    // penned[k] is the chance the sheep is penned within k moves
+    pub penned: Vec<f32>,
+    // lost[k] is the chance the sheep has run into the dog within k moves
+    pub lost: Vec<f32>,
+}
+
+impl StepsToPen {
+    // the fewest moves that pen the sheep with at least this chance, or None if the horizon
+    // wasn't long enough to tell
+    pub fn percentile(&self, chance: f32) -> Option<usize> {
+        self.penned.iter().position(|penned| *penned >= chance)
+    }
+
+    // the chance the game is still going after the horizon
+    pub fn unfinished(&self) -> f32 {
+        let last = self.penned.len() - 1;
+        1.0 - self.penned[last] - self.lost[last]
+    }
+}
+
+// every sheep start with the dog at its usual start, equally likely. the sheep starting on top of
+// the dog is left out
+pub(crate) fn uniform_starts(config: &FieldConfig) -> Vec<((Sheep, Dog), f32)> {
+    let dog = config.dog_start;
+    let terrain = Arc::new(Terrain::new(config));
+    let mut starts = Vec::new();
+    for y in 0..config.size {
+        for x in 0..config.size {
+            let field = Field::on(&terrain, vec![Sheep::at(x, y)], vec![dog]);
+            if field.is_valid() && !field.sheep_won() {
+                starts.push((Sheep::at(x, y), dog));
+            }
+        }
+    }
+    let chance = 1.0 / starts.len() as f32;
+    starts.into_iter().map(|start| (start, chance)).collect()
+}
+
+// push the distribution over states forward one move at a time for horizon moves, with the dog
+// following dog_policy, starting from the given start states and their chances. pass a single
+// start with chance 1 for the distribution from that state alone
+pub(crate) fn steps_to_pen(
+    config: &FieldConfig,
+    sheep_policy: &dyn SheepPolicy,
+    dog_policy: &dyn DogPolicy,
+    starts: &[((Sheep, Dog), f32)],
+    horizon: usize,
+) -> StepsToPen {
+    let chain = policy_chain(config, sheep_policy, dog_policy);
+    let count = chain.terrain.state_count();
+    let mut distribution = vec![0.0f64; count];
+    for (state, chance) in starts {
+        let index = chain.terrain.index(*state);
+        assert!(chain.valid[index], "{:?} is not a valid start", state);
+        distribution[index] += *chance as f64;
+    }
+
+    let mut penned = 0.0;
+    let mut lost = 0.0;
+    let mut steps = StepsToPen {
+        penned: Vec::with_capacity(horizon + 1),
+        lost: Vec::with_capacity(horizon + 1),
+    };
+    for step in 0..=horizon {
+        // whatever reached the end of the game stops there
+        for (index, chance) in distribution.iter_mut().enumerate() {
+            if *chance == 0.0 {
+                continue;
+            }
+            if chain.penned[index] {
+                penned += *chance;
+                *chance = 0.0;
+            } else if chain.lost[index] {
+                lost += *chance;
+                *chance = 0.0;
+            }
+        }
+        steps.penned.push(penned as f32);
+        steps.lost.push(lost as f32);
+        if step == horizon {
+            break;
+        }
+        let mut next_distribution = vec![0.0f64; count];
+        for (index, chance) in distribution.iter().enumerate() {
+            if *chance == 0.0 {
+                continue;
+            }
+            for (next, probability) in chain.successors[index].iter() {
+                next_distribution[*next] += chance * probability;
+            }
+        }
+        distribution = next_distribution;
+    }
+    steps
+}
+
+// what the dogs are expected to take from their start, averaged over every sheep start they are
+// sure to pen the sheep from, and how many starts they aren't, going by expected, which has
+// nothing for the sheep starts that aren't states. the average is None when no start is sure. the
+// sheep starting on top of a dog is left out
+pub(crate) fn average_from_start(
+    config: &FieldConfig,
+    dogs: &[Dog],
+    expected: impl Fn(Sheep) -> Option<f32>,
+) -> (Option<f32>, usize) {
+    let mut total = 0.0;
+    let mut starts = 0;
+    let mut improper = 0;
+    for y in 0..config.size {
+        for x in 0..config.size {
+            if dogs.iter().any(|dog| (x, y) == (dog.x, dog.y)) {
+                continue;
+            }
+            match expected(Sheep::at(x, y)) {
+                Some(moves) if moves.is_finite() => {
+                    total += moves;
+                    starts += 1;
+                }
+                Some(_) => improper += 1,
+                None => (),
+            }
+        }
+    }
+    let average = if starts > 0 {
+        Some(total / starts as f32)
+    } else {
+        None
+    };
+    (average, improper)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::mdp::SolverOptions;
+    use crate::sheep_policy::Flee;
+    use crate::solve_markov::generate_dog_policy;
+    use crate::solve_markov::generate_optimal_utlility;
+
+    // a sheep that gets one step closer to the pen half the time takes two moves a step. state 3
+    // is the pen, 4 gets there or to 5 with even chances and 5 never gets anywhere, so neither is
+    // sure to end. 6 isn't a state at all
+    #[test]
+    fn a_chain_takes_its_closed_form_time() {
+        let valid = [true, true, true, true, true, true, false];
+        let penned = [false, false, false, true, false, false, false];
+        let successors = vec![
+            vec![(0, 0.5), (1, 0.5)],
+            vec![(1, 0.5), (2, 0.5)],
+            vec![(2, 0.5), (3, 0.5)],
+            vec![],
+            vec![(3, 0.5), (5, 0.5)],
+            vec![(5, 1.0)],
+            vec![],
+        ];
+        let (moves, report) = expected_moves(&valid, &penned, &successors);
+        assert!(report.converged);
+        for (index, expected) in [6.0, 4.0, 2.0, 0.0].iter().enumerate() {
+            assert!((moves[index] - expected).abs() <= report.error_bound as f64 + 1e-9);
+            assert!((moves[index] - expected).abs() < 1e-6);
+        }
+        assert_eq!(moves[4], f64::INFINITY);
+        assert_eq!(moves[5], f64::INFINITY);
+    }
+
+    // lure never plans ahead, so there are starts it herds the sheep around forever from
+    #[test]
+    fn lure_can_fail_to_pen_the_sheep() {
+        let config = FieldConfig::new(7);
+        let policy = Flee::default();
+        let lure = Lure {
+            sheep_policy: &policy,
+        };
+        let (moves, _) = evaluate_dog_policy(&config, &policy, &lure);
+        let stuck = uniform_starts(&config)
+            .into_iter()
+            .filter(|(state, _)| moves.get(*state) == Some(f32::INFINITY))
+            .collect::<Vec<_>>();
+        assert!(!stuck.is_empty());
+        let steps = steps_to_pen(&config, &policy, &lure, &[(stuck[0].0, 1.0)], 500);
+        assert!(steps.penned[500] < 0.999);
+    }
+
+    // the expected moves add up the chances of the game still going after each move
+    #[test]
+    fn the_expected_moves_are_the_mean_of_steps_to_pen() {
+        let config = FieldConfig::new(7);
+        let policy = Flee::default();
+        let table = solved_table(&config, &policy);
+        let (moves, report) = evaluate_dog_policy(&config, &policy, &table);
+        assert!(report.converged);
+        for (state, _) in uniform_starts(&config).into_iter().step_by(5) {
+            let expected = moves.get(state).unwrap();
+            assert!(expected.is_finite());
+            let steps = steps_to_pen(&config, &policy, &table, &[(state, 1.0)], 2000);
+            assert!(steps.unfinished() < 1e-6);
+            let mean: f32 = steps.penned.iter().map(|penned| 1.0 - penned).sum();
+            assert!(
+                (mean - expected).abs() <= 1e-3 * expected.max(1.0),
+                "{:?}: {} against {}",
+                state,
+                mean,
+                expected
+            );
+        }
+    }
+
+    fn solved_table(config: &FieldConfig, policy: &dyn SheepPolicy) -> PolicyTable {
+        let options = SolverOptions::default();
+        let (map, _) = generate_optimal_utlility(config, policy, &options);
+        generate_dog_policy(config, policy, &map, &options)
+    }
+
+    // the chances only pile up, and never to more than certainty
+    #[test]
+    fn steps_to_pen_only_piles_the_chances_up() {
+        let config = FieldConfig::new(7);
+        let policy = Flee::default();
+        let lure = Lure {
+            sheep_policy: &policy,
+        };
+        let table = solved_table(&config, &policy);
+        let dog_policies: [&dyn DogPolicy; 2] = [&table, &lure];
+        for dog_policy in dog_policies {
+            let steps = steps_to_pen(&config, &policy, dog_policy, &uniform_starts(&config), 100);
+            for step in 1..=100 {
+                assert!(steps.penned[step] >= steps.penned[step - 1]);
+                assert!(steps.lost[step] >= steps.lost[step - 1]);
+                assert!(steps.penned[step] + steps.lost[step] <= 1.0 + 1e-6);
+            }
+            assert!(steps.unfinished() >= -1e-6);
+        }
+    }
+
+    // the sheep starts steps_to_pen spreads its chances over are the ones average_from_start
+    // averages over, so the mean time to pen from them is the average expected time
+    #[test]
+    fn the_average_from_start_is_the_mean_of_steps_to_pen() {
+        let config = FieldConfig::new(7);
+        let policy = Flee::default();
+        let table = solved_table(&config, &policy);
+        let (moves, _) = evaluate_dog_policy(&config, &policy, &table);
+        let dog = config.dog_start;
+        let (average, improper) =
+            average_from_start(&config, &[dog], |sheep| moves.get((sheep, dog)));
+        assert_eq!(improper, 0);
+        let average = average.unwrap();
+        let steps = steps_to_pen(&config, &policy, &table, &uniform_starts(&config), 2000);
+        assert!(steps.unfinished() < 1e-6);
+        let mean: f32 = steps.penned.iter().map(|penned| 1.0 - penned).sum();
+        assert!(
+            (mean - average).abs() < 1e-3 * average,
+            "{} against {}",
+            mean,
+            average
+        );
+    }
+
+    #[test]
+    fn there_is_no_average_when_every_start_can_fail() {
+        let config = FieldConfig::new(7);
+        let dog = config.dog_start;
+        let (average, improper) = average_from_start(&config, &[dog], |_| Some(f32::INFINITY));
+        assert_eq!(average, None);
+        assert_eq!(improper, 7 * 7 - 1);
+        let (average, improper) = average_from_start(&config, &[dog], |_| None);
+        assert_eq!((average, improper), (None, 0));
+    }
+}
@@ -1,7 +1,15 @@
-use rand::{self, seq::SliceRandom};
+use rand::{seq::SliceRandom, Rng};
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+use crate::map_file::format_field;
+use crate::move_set::MoveSet;
+use crate::pen::Pen;
+use crate::sheep_policy::SheepPolicy;
+use crate::terrain::Terrain;
+
+use std::sync::Arc;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
 pub struct Sheep {
     pub x: i32,
     pub y: i32,
@@ -25,7 +33,7 @@ impl Sheep {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
 pub struct Dog {
     pub x: i32,
     pub y: i32,
@@ -49,333 +57,412 @@ impl Dog {
     }
 }
 
+// the layout every part of the pipeline reads: grid size, the pen, obstacles and start positions
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct FieldConfig {
+    pub size: i32,
+    pub pen: Pen,
+    pub obstacles: Vec<(i32, i32)>,
+    pub sheep_start: Sheep,
+    pub dog_start: Dog,
+    pub dog_moves: MoveSet,
+    pub sheep_moves: MoveSet,
+}
+
+impl FieldConfig {
+    pub fn new(size: i32) -> Self {
+        assert!(
+            size >= 5 && size % 2 == 1,
+            "field size must be odd and at least 5, got {}",
+            size
+        );
+        let pen = Pen::centered(size);
+        Self {
+            size,
+            sheep_start: Sheep::at(0, 0),
+            dog_start: outside_mouth(&pen, size),
+            pen,
+            obstacles: Vec::new(),
+            dog_moves: MoveSet::moore(),
+            sheep_moves: MoveSet::von_neumann(),
+        }
+    }
+
+    // swap in a different pen; the dog starts a few cells out from its mouth
+    pub fn with_pen(mut self, pen: Pen) -> Self {
+        for (x, y) in pen.fences() {
+            assert!(
+                self.contains(x, y),
+                "pen fence ({}, {}) is off the field",
+                x,
+                y
+            );
+        }
+        assert!(
+            self.contains(pen.goal.0, pen.goal.1),
+            "pen goal {:?} is off the field",
+            pen.goal
+        );
+        self.dog_start = outside_mouth(&pen, self.size);
+        self.pen = pen;
+        self
+    }
+
+    pub fn with_obstacles(mut self, obstacles: Vec<(i32, i32)>) -> Self {
+        for (x, y) in obstacles.iter() {
+            assert!(
+                self.contains(*x, *y),
+                "obstacle ({}, {}) is off the field",
+                x,
+                y
+            );
+        }
+        self.obstacles = obstacles;
+        self
+    }
+
+    pub fn with_dog_moves(mut self, dog_moves: MoveSet) -> Self {
+        self.dog_moves = dog_moves;
+        self
+    }
+
+    pub fn with_sheep_moves(mut self, sheep_moves: MoveSet) -> Self {
+        self.sheep_moves = sheep_moves;
+        self
+    }
+
+    // the pack lines up across the usual start, alternating sides two cells apart. a pack too
+    // big for that line to fit on the field is refused rather than stacked on the edge
+    pub fn pack_start(&self, pack_size: usize) -> Vec<Dog> {
+        let offset = self.pen.opening.offset();
+        let across = (offset.1.abs(), offset.0.abs());
+        let clamp = |value: i32| value.clamp(0, self.size - 1);
+        let dogs: Vec<Dog> = (0..pack_size as i32)
+            .map(|index| {
+                let side = if index % 2 == 1 { 1 } else { -1 };
+                let distance = side * 2 * ((index + 1) / 2);
+                Dog::at(
+                    clamp(self.dog_start.x + distance * across.0),
+                    clamp(self.dog_start.y + distance * across.1),
+                )
+            })
+            .collect();
+        for (index, dog) in dogs.iter().enumerate() {
+            assert!(
+                !dogs[..index].contains(dog),
+                "a pack of {} dogs doesn't fit across the start of a {}x{} field",
+                pack_size,
+                self.size,
+                self.size
+            );
+        }
+        dogs
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.size && y < self.size
+    }
+}
+
+fn outside_mouth(pen: &Pen, size: i32) -> Dog {
+    let offset = pen.opening.offset();
+    let clamp = |value: i32| value.clamp(0, size - 1);
+    Dog::at(
+        clamp(pen.goal.0 + 3 * offset.0),
+        clamp(pen.goal.1 + 3 * offset.1),
+    )
+}
+
+impl Default for FieldConfig {
+    fn default() -> Self {
+        Self::new(31)
+    }
+}
+
+// the animals on a field; everything that stays put lives in the shared terrain, so a field is
+// cheap to clone and every move only copies a few positions
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Field {
-    pub grid: Vec<Vec<Cell>>,
-    pub sheep: Sheep,
-    pub dog: Dog,
+    pub flock: Vec<Sheep>,
+    pub dogs: Vec<Dog>,
+    terrain: Arc<Terrain>,
 }
 
 impl Field {
-    pub fn new() -> Self {
-        // initialize an empty field, then make the pen
-        let mut empty_grid = vec![];
-        let size: usize = 31;
-        let middle = (size - 1) / 2;
-        let mut sheep = Sheep::new();
-        let mut dog = Dog::new();
-
-        //sheep starting position
-        sheep.x = 0;
-        sheep.y = 0;
-
-        // dog starting position
-        dog.y = size as i32 - 1;
-        dog.x = size as i32 - 1;
-
-        for i in 0..size {
-            let mut row = Vec::new();
-            for j in 0..size {
-                row.push(Cell::new(j as i32, i as i32, Entity::Empty));
-            }
-            empty_grid.push(row);
+    pub fn new(config: &FieldConfig) -> Self {
+        Field::with(config, config.sheep_start, config.dog_start)
+    }
+
+    pub fn with(config: &FieldConfig, sheep: Sheep, dog: Dog) -> Self {
+        Field::with_pack(config, vec![sheep], vec![dog])
+    }
+
+    pub fn with_flock(config: &FieldConfig, flock: Vec<Sheep>, dog: Dog) -> Self {
+        Field::with_pack(config, flock, vec![dog])
+    }
+
+    pub fn with_pack(config: &FieldConfig, flock: Vec<Sheep>, dogs: Vec<Dog>) -> Self {
+        Field::on(&Arc::new(Terrain::new(config)), flock, dogs)
+    }
+
+    // place the animals on a terrain that was already built, which is what anything looping
+    // over many states should use instead of laying the field out again every time
+    pub fn on(terrain: &Arc<Terrain>, flock: Vec<Sheep>, dogs: Vec<Dog>) -> Self {
+        assert!(!flock.is_empty(), "a field needs at least one sheep");
+        assert!(!dogs.is_empty(), "a field needs at least one dog");
+        Self {
+            flock,
+            dogs,
+            terrain: Arc::clone(terrain),
         }
+    }
 
-        // left wall
-        empty_grid[middle - 1][middle - 1] =
-            Cell::new(middle as i32 - 1, middle as i32 - 1, Entity::Fence);
-        empty_grid[middle][middle - 1] = Cell::new(middle as i32 - 1, middle as i32, Entity::Fence);
-        empty_grid[middle + 1][middle - 1] =
-            Cell::new(middle as i32 - 1, middle as i32 + 1, Entity::Fence);
-
-        // right wall
-        empty_grid[middle - 1][middle + 1] =
-            Cell::new(middle as i32 + 1, middle as i32 - 1, Entity::Fence);
-        empty_grid[middle][middle + 1] = Cell::new(middle as i32 + 1, middle as i32, Entity::Fence);
-        empty_grid[middle + 1][middle + 1] =
-            Cell::new(middle as i32 + 1, middle as i32 + 1, Entity::Fence);
-
-        // bottom of pen
-        empty_grid[middle + 1][middle] = Cell::new(middle as i32 + 1, middle as i32, Entity::Fence);
-
-        // put the sheep on the grid
-        empty_grid[sheep.y as usize][sheep.x as usize] = sheep.as_cell();
-
-        // test if the sheeps movement actually works
-        empty_grid[dog.y as usize][dog.x as usize] = dog.as_cell();
-
-        return Self {
-            grid: empty_grid,
-            sheep,
-            dog,
-        };
+    pub fn config(&self) -> &FieldConfig {
+        &self.terrain.config
     }
 
-    pub fn is_valid(&self) -> bool {
-        let size: usize = self.grid.len();
-        let middle = (size - 1) / 2;
-        let grid = self.grid.clone();
+    pub fn terrain(&self) -> &Arc<Terrain> {
+        &self.terrain
+    }
 
-        // left wall
-        let left_wall = grid[middle - 1][middle - 1].entity == Entity::Fence
-            && grid[middle][middle - 1].entity == Entity::Fence
-            && grid[middle + 1][middle - 1].entity == Entity::Fence;
+    // the first sheep of the flock, which is the only one the single sheep solver knows about
+    pub fn sheep(&self) -> Sheep {
+        self.flock[0]
+    }
 
-        // right wall
-        let right_wall = grid[middle - 1][middle + 1].entity == Entity::Fence
-            && grid[middle][middle + 1].entity == Entity::Fence
-            && grid[middle + 1][middle + 1].entity == Entity::Fence;
+    // likewise the first dog of the pack
+    pub fn dog(&self) -> Dog {
+        self.dogs[0]
+    }
 
-        // bottom of pen
-        let bottom_wall = grid[middle + 1][middle].entity == Entity::Fence;
+    // what the cell shows, dogs drawn over sheep drawn over the terrain; None off the field
+    pub fn entity_at(&self, x: i32, y: i32) -> Option<Entity> {
+        let ground = self.terrain.entity(x, y)?;
+        if self.dogs.iter().any(|dog| dog.x == x && dog.y == y) {
+            Some(Entity::Dog)
+        } else if self.flock.iter().any(|sheep| sheep.x == x && sheep.y == y) {
+            Some(Entity::Sheep)
+        } else {
+            Some(ground)
+        }
+    }
 
-        return left_wall && right_wall && bottom_wall;
+    // false once a sheep or dog has been placed on top of part of the pen or an obstacle
+    pub fn is_valid(&self) -> bool {
+        self.flock
+            .iter()
+            .all(|sheep| self.terrain.is_open(sheep.x, sheep.y))
+            && self
+                .dogs
+                .iter()
+                .all(|dog| self.terrain.is_open(dog.x, dog.y))
     }
 
+    // the dogs lose as soon as any sheep runs into one of them
     pub fn sheep_won(&self) -> bool {
-        self.dog.x == self.sheep.x && self.dog.y == self.sheep.y
-    }
-
-    pub fn with(sheep: Sheep, dog: Dog) -> Self {
-        let mut field = Field::new();
-        let old_dog = field.dog;
-        let old_sheep = field.sheep;
-        field.grid[old_sheep.y as usize][old_sheep.x as usize].entity = Entity::Empty;
-        field.grid[old_dog.y as usize][old_dog.x as usize].entity = Entity::Empty;
-        field.sheep = sheep;
-        field.dog = dog;
-        field.grid[sheep.y as usize][sheep.x as usize].entity = Entity::Sheep;
-        field.grid[dog.y as usize][dog.x as usize].entity = Entity::Dog;
-        field
-    }
-    // dog related functions
-
-    fn get_dog_moves(&self) -> Vec<Cell> {
-        let x = self.dog.x;
-        let y = self.dog.y;
-        let mut moves = Vec::<Cell>::new();
-        for row in y - 1..y + 2 {
-            for column in x - 1..x + 2 {
-                if column == x && row == y {
-                    continue;
-                }
-                match self.grid.get(row as usize) {
-                    Some(row) => match row.get(column as usize) {
-                        Some(cell) => match cell.entity {
-                            Entity::Empty => {
-                                let mut dog_cell = cell.clone();
-                                dog_cell.entity = Entity::Dog;
-                                moves.push(dog_cell);
-                            }
-                            _ => continue,
-                        },
-                        None => continue,
-                    },
-                    None => continue,
-                };
-            }
-        }
-        moves
+        self.flock.iter().any(|sheep| {
+            self.dogs
+                .iter()
+                .any(|dog| dog.x == sheep.x && dog.y == sheep.y)
+        })
     }
 
-    fn get_dog_moves_ignore_sheep(&self) -> Vec<Cell> {
-        let x = self.dog.x;
-        let y = self.dog.y;
-        let mut moves = Vec::<Cell>::new();
-        for row in y - 1..y + 2 {
-            for column in x - 1..x + 2 {
-                if column == x && row == y {
-                    continue;
-                }
-                match self.grid.get(row as usize) {
-                    Some(row) => match row.get(column as usize) {
-                        Some(cell) => match cell.entity {
-                            Entity::Empty | Entity::Sheep => {
-                                let mut dog_cell = cell.clone();
-                                dog_cell.entity = Entity::Dog;
-                                moves.push(dog_cell);
-                            }
-                            _ => continue,
-                        },
-                        None => continue,
-                    },
-                    None => continue,
-                };
-            }
-        }
-        moves
+    // dog related functions, where index picks the dog out of the pack
+
+    // where the dog could go if the rest of the pack weren't there
+    fn get_dog_moves(&self, index: usize) -> Vec<Cell> {
+        let empty = |x: i32, y: i32| {
+            self.terrain.entity(x, y) == Some(Entity::Empty)
+                && !self.flock.iter().any(|sheep| sheep.x == x && sheep.y == y)
+        };
+        self.dog_targets(index, empty, empty)
+    }
+
+    fn get_dog_moves_ignore_sheep(&self, index: usize) -> Vec<Cell> {
+        let open =
+            |x: i32, y: i32| matches!(self.entity_at(x, y), Some(Entity::Empty | Entity::Sheep));
+        self.dog_targets(index, open, open)
+    }
+
+    fn dog_targets(
+        &self,
+        index: usize,
+        is_clear: impl Fn(i32, i32) -> bool,
+        can_land: impl Fn(i32, i32) -> bool,
+    ) -> Vec<Cell> {
+        let dog = self.dogs[index];
+        self.config()
+            .dog_moves
+            .targets((dog.x, dog.y), is_clear, can_land)
+            .into_iter()
+            .map(|(x, y)| Cell::new(x, y, Entity::Dog))
+            .collect()
     }
 
-    pub fn move_dog_to(&self, cell: Cell) -> Field {
+    pub fn move_dog_to(&self, index: usize, cell: Cell) -> Field {
         let mut new_field = self.clone();
-        let old_dog = self.dog.as_cell();
-        new_field.grid[cell.y as usize][cell.x as usize] = cell;
-        new_field.grid[old_dog.y as usize][old_dog.x as usize] =
-            Cell::new(old_dog.x, old_dog.y, Entity::Empty);
-        new_field.dog.x = cell.x;
-        new_field.dog.y = cell.y;
+        new_field.dogs[index] = Dog::at(cell.x, cell.y);
         new_field
     }
 
+    // every joint move of the pack. the dogs move at once, so each one picks from its own moves
+    // on the board as it was and only the joint moves that end with two dogs on one cell are left
+    // out; which dog comes first makes no difference
     pub fn get_dog_states(&self) -> Vec<Field> {
-        let possible_moves = self.get_dog_moves();
-        let mut states = Vec::<Field>::new();
-        for possible_move in possible_moves {
-            states.push(self.move_dog_to(possible_move));
+        let mut states = vec![self.clone()];
+        for index in 0..self.dogs.len() {
+            let dog_moves = self.get_dog_moves(index);
+            let mut next_states = Vec::<Field>::new();
+            for state in states {
+                for possible_move in dog_moves.iter() {
+                    next_states.push(state.move_dog_to(index, *possible_move));
+                }
+            }
+            states = next_states;
         }
+        states.retain(|state| {
+            state
+                .dogs
+                .iter()
+                .enumerate()
+                .all(|(index, dog)| !state.dogs[..index].contains(dog))
+        });
         states
     }
 
-    // sheep related functions
-
-    pub fn get_sheep_moves(&self) -> Vec<Cell> {
-        let x = self.sheep.x;
-        let y = self.sheep.y;
-        let mut moves = Vec::<Cell>::new();
-        for row in y - 1..y + 2 {
-            for column in x - 1..x + 2 {
-                if (column != x && row != y) || (column == x && row == y) {
-                    continue;
-                }
-                match self.grid.get(row as usize) {
-                    Some(grid_row) => match grid_row.get(column as usize) {
-                        Some(cell) => match cell.entity {
-                            Entity::Empty | Entity::Dog => {
-                                let mut sheep_cell = cell.clone();
-                                sheep_cell.entity = Entity::Sheep;
-                                moves.push(sheep_cell);
-                            }
-                            _ => continue,
-                        },
-                        None => continue,
-                    },
-                    None => continue,
-                };
-            }
-        }
-        moves
+    // sheep related functions, where index picks the sheep out of the flock
+
+    // sheep can't step onto or over each other, but may run into a dog
+    pub fn get_sheep_moves(&self, index: usize) -> Vec<Cell> {
+        let sheep = self.flock[index];
+        self.config()
+            .sheep_moves
+            .targets(
+                (sheep.x, sheep.y),
+                |x, y| self.entity_at(x, y) == Some(Entity::Empty),
+                |x, y| {
+                    self.terrain.is_open(x, y)
+                        && !self.flock.iter().any(|other| other.x == x && other.y == y)
+                },
+            )
+            .into_iter()
+            .map(|(x, y)| Cell::new(x, y, Entity::Sheep))
+            .collect()
     }
 
     pub fn get_sheep_moves_bfs(&self) -> Vec<Field> {
         let mut states = Vec::<Field>::new();
-        let possible_moves = self.get_sheep_moves();
+        let possible_moves = self.get_sheep_moves(0);
         for possible_move in possible_moves {
-            states.push(self.move_sheep_to(possible_move));
+            states.push(self.move_sheep_to(0, possible_move));
         }
         states
     }
 
     pub fn get_dog_moves_bfs(&self) -> Vec<Field> {
         let mut states = Vec::<Field>::new();
-        let possible_moves = self.get_dog_moves_ignore_sheep();
+        let possible_moves = self.get_dog_moves_ignore_sheep(0);
         for possible_move in possible_moves {
-            states.push(self.move_dog_to(possible_move));
+            states.push(self.move_dog_to(0, possible_move));
         }
         states
     }
 
-    pub fn get_sheep_states(&self) -> Vec<Field> {
-        let mut states = Vec::<Field>::new();
-        let mut possible_moves = Vec::<Cell>::new();
-
-        if self.dog_in_view().is_some() {
-            let position = (self.dog.x, self.dog.y);
-            let x = self.sheep.x;
-            let y = self.sheep.y;
+    // every field the sheep's reaction can lead to, each one listed once with its probability.
+    // the solver's backup and the simulation both go through here, so they share the dynamics.
+    // staying put is an outcome like any other: it carries whatever mass the policy puts on the
+    // sheep's own cell, and all of it when the sheep is penned or has nowhere to go
+    pub fn sheep_transitions(&self, index: usize, policy: &dyn SheepPolicy) -> Vec<(Field, f32)> {
+        let sheep = self.flock[index];
 
-            for sheep_move in self.get_sheep_moves() {
-                if (sheep_move.x - position.0).abs() < (x - position.0).abs() {
-                    possible_moves.push(sheep_move);
-                }
-                if (sheep_move.y - position.1).abs() < (y - position.1).abs() {
-                    possible_moves.push(sheep_move);
-                }
-            }
+        // penned sheep settle down and stay put
+        if self.terrain.encloses(sheep.x, sheep.y) {
+            return vec![(self.clone(), 1.0)];
+        }
 
-            if possible_moves.len() == 0 {
-                possible_moves = self.get_sheep_moves();
+        let mut outcomes = Vec::<(Cell, f32)>::new();
+        for (cell, probability) in policy.next_cells(self, index) {
+            match outcomes
+                .iter_mut()
+                .find(|(outcome, _)| outcome.x == cell.x && outcome.y == cell.y)
+            {
+                Some((_, total)) => *total += probability,
+                None => outcomes.push((cell, probability)),
             }
-        } else {
-            possible_moves = self.get_sheep_moves();
         }
-        for possible_move in possible_moves {
-            states.push(self.move_sheep_to(possible_move));
+        outcomes.retain(|(_, probability)| *probability > 0.0);
+        if outcomes.is_empty() {
+            return vec![(self.clone(), 1.0)];
+        }
+
+        let total: f32 = outcomes.iter().map(|(_, probability)| probability).sum();
+        let mut states = Vec::<(Field, f32)>::new();
+        for (cell, probability) in outcomes {
+            let state = if cell.x == sheep.x && cell.y == sheep.y {
+                self.clone()
+            } else {
+                self.move_sheep_to(index, cell)
+            };
+            states.push((state, probability / total));
         }
         states
     }
 
-    pub fn move_sheep_to(&self, cell: Cell) -> Field {
+    // draw one outcome of sheep_transitions
+    pub fn sample_sheep_transition(
+        &self,
+        index: usize,
+        policy: &dyn SheepPolicy,
+        rng: &mut impl Rng,
+    ) -> Field {
+        let states = self.sheep_transitions(index, policy);
+        let (state, _) = states
+            .choose_weighted(rng, |(_, probability)| *probability)
+            .unwrap();
+        state.clone()
+    }
+
+    pub fn move_sheep_to(&self, index: usize, cell: Cell) -> Field {
         let mut new_field = self.clone();
-        let old_sheep = self.sheep.as_cell();
-        new_field.grid[cell.y as usize][cell.x as usize] = cell;
-        new_field.grid[old_sheep.y as usize][old_sheep.x as usize] =
-            Cell::new(old_sheep.x, old_sheep.y, Entity::Empty);
-        new_field.sheep.x = cell.x;
-        new_field.sheep.y = cell.y;
+        new_field.flock[index] = Sheep::at(cell.x, cell.y);
         new_field
     }
 
-    pub fn move_sheep(&mut self) {
-        // get the squares nearby and see if the dog is in one of them
-        let states = self.get_sheep_states();
-        let chosen_move = states.choose(&mut rand::thread_rng()).unwrap();
-        self.grid = chosen_move.grid.clone();
-        self.sheep = chosen_move.sheep;
-    }
-
-    fn get_sheep_view(&self) -> Vec<Cell> {
-        let sheep_postion = (self.sheep.x, self.sheep.y);
-        let lower_bound_x = sheep_postion.0 - 2;
-        let upper_bound_x = sheep_postion.0 + 3;
-        let lower_bound_y = sheep_postion.1 - 2;
-        let upper_bound_y = sheep_postion.1 + 3;
-        let mut result = Vec::new();
-        for i in lower_bound_y..upper_bound_y {
-            for j in lower_bound_x..upper_bound_x {
-                let cell_view = self.grid.get(i as usize);
-                match cell_view {
-                    Some(row) => match row.get(j as usize) {
-                        Some(cell) => result.push(cell.clone()),
-                        None => continue,
-                    },
-                    None => continue,
-                }
-            }
+    // the flock moves one sheep at a time, each one reacting to where the others ended up
+    pub fn move_sheep(&mut self, policy: &dyn SheepPolicy, rng: &mut impl Rng) {
+        for index in 0..self.flock.len() {
+            *self = self.sample_sheep_transition(index, policy, rng);
         }
-        result
     }
 
-    fn dog_in_view(&self) -> Option<Cell> {
-        let view = self.get_sheep_view();
-        for cell in view.iter() {
-            if cell.entity == Entity::Dog {
-                return Some(cell.clone());
-            }
-        }
-        None
+    // the closest dog within view_radius of the sheep in either direction, which is the one it
+    // reacts to; ties go to the dog higher up, then further left
+    pub fn dog_in_view(&self, index: usize, view_radius: i32) -> Option<Cell> {
+        let sheep = self.flock[index];
+        self.dogs
+            .iter()
+            .filter(|dog| {
+                (dog.x - sheep.x).abs() <= view_radius && (dog.y - sheep.y).abs() <= view_radius
+            })
+            .min_by_key(|dog| {
+                (
+                    (dog.x - sheep.x).abs() + (dog.y - sheep.y).abs(),
+                    dog.y,
+                    dog.x,
+                )
+            })
+            .map(|dog| dog.as_cell())
     }
 
+    // every sheep in the flock has to be penned
     pub fn dog_won(&self) -> bool {
-        (self.sheep.x == self.grid.len() as i32 / 2 && self.sheep.y == self.grid.len() as i32 / 2)
-            || ((self.dog.x == self.grid.len() as i32 / 2
-                && self.dog.y == self.grid.len() as i32 / 2)
-                && (self.sheep.x == self.grid.len() as i32 / 2
-                    && self.sheep.y == self.grid.len() as i32 / 2 - 1))
+        self.flock
+            .iter()
+            .all(|sheep| self.dogs.iter().any(|dog| self.terrain.holds(*sheep, *dog)))
     }
 
     pub fn print(&self) {
-        for row in self.grid.iter() {
-            for cell in row {
-                match cell.entity {
-                    Entity::Empty => print!("_ "),
-                    Entity::Fence => print!("☐ "),
-                    Entity::Sheep => print!("S "),
-                    Entity::Dog => print!("D "),
-                    // _ => print!("  "),
-                }
-            }
-            println!();
-        }
+        print!("{}", format_field(self));
     }
 }
 
@@ -383,14 +470,15 @@ impl Field {
 pub enum Entity {
     Empty,
     Fence,
+    Obstacle,
     Sheep,
     Dog,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash, PartialEq, Eq)]
 pub struct Cell {
-    x: i32,
-    y: i32,
+    pub x: i32,
+    pub y: i32,
     pub entity: Entity,
 }
 
@@ -399,3 +487,150 @@ impl Cell {
         Self { x, y, entity }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::pen::PenShape;
+    use crate::pen::Side;
+    use crate::sheep_policy::Flee;
+    use crate::sheep_policy::Lazy;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn sheep_transitions_list_each_outcome_once() {
+        let config = FieldConfig::new(7);
+        let policy = Lazy::new(Box::new(Flee::default()), 0.25);
+        for sheep in 0..config.size * config.size {
+            for dog in 0..config.size * config.size {
+                let sheep = Sheep::at(sheep % config.size, sheep / config.size);
+                let dog = Dog::at(dog % config.size, dog / config.size);
+                let field = Field::with(&config, sheep, dog);
+                if !field.is_valid() {
+                    continue;
+                }
+                let outcomes = field.sheep_transitions(0, &policy);
+                for (index, (outcome, probability)) in outcomes.iter().enumerate() {
+                    assert!(*probability > 0.0);
+                    assert!(outcomes[..index].iter().all(|(other, _)| other != outcome));
+                }
+                let total: f32 = outcomes.iter().map(|(_, probability)| probability).sum();
+                assert!(
+                    (total - 1.0).abs() < 1e-6,
+                    "{:?} sums to {}",
+                    (sheep, dog),
+                    total
+                );
+            }
+        }
+    }
+
+    // two dogs side by side on the top edge of a 7x7 field each have five moves, one of them
+    // onto the other's cell. of the 25 pairs only the two that end on one cell are ruled out, so
+    // one dog can follow the other or swap with it
+    #[test]
+    fn pack_moves_are_every_pair_of_dog_moves() {
+        let config = FieldConfig::new(7);
+        let joint_moves = |dogs: Vec<Dog>| {
+            let field = Field::with_pack(&config, vec![Sheep::at(0, 6)], dogs);
+            let mut moves: Vec<Vec<Dog>> = field
+                .get_dog_states()
+                .into_iter()
+                .map(|state| {
+                    let mut dogs = state.dogs;
+                    dogs.sort();
+                    dogs
+                })
+                .collect();
+            moves.sort();
+            moves
+        };
+        let moves = joint_moves(vec![Dog::at(2, 0), Dog::at(3, 0)]);
+        assert_eq!(moves.len(), 23);
+        assert!(moves.contains(&vec![Dog::at(3, 0), Dog::at(4, 0)]));
+        assert!(moves.contains(&vec![Dog::at(2, 0), Dog::at(3, 0)]));
+        assert!(!moves.iter().any(|dogs| dogs[0] == dogs[1]));
+        assert_eq!(moves, joint_moves(vec![Dog::at(3, 0), Dog::at(2, 0)]));
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't fit")]
+    fn a_pack_that_does_not_fit_is_refused() {
+        FieldConfig::new(5).pack_start(4);
+    }
+
+    // a cup three cells wide, with room for three sheep
+    fn wide_pen_config() -> FieldConfig {
+        FieldConfig::new(9).with_pen(Pen::new(
+            (4, 4),
+            PenShape::Cup { width: 3, depth: 1 },
+            Side::Up,
+        ))
+    }
+
+    #[test]
+    fn a_flock_is_penned_once_every_sheep_is() {
+        let config = wide_pen_config();
+        let dog = config.dog_start;
+        let inside = [Sheep::at(3, 4), Sheep::at(5, 4)];
+        assert!(Field::with_flock(&config, inside.to_vec(), dog).dog_won());
+        assert!(!Field::with_flock(&config, vec![inside[0], Sheep::at(0, 0)], dog).dog_won());
+        assert!(!Field::with_flock(&config, vec![Sheep::at(0, 0), inside[1]], dog).dog_won());
+    }
+
+    // the dog wanders at random while three sheep react, one after another
+    #[test]
+    fn sheep_never_share_a_cell() {
+        let config = wide_pen_config();
+        let policy = Flee::default();
+        let mut rng = StdRng::seed_from_u64(0);
+        let flock = vec![Sheep::at(1, 1), Sheep::at(2, 1), Sheep::at(1, 2)];
+        let start = Field::with_flock(&config, flock, Dog::at(0, 0));
+        let mut field = start.clone();
+        for _ in 0..2000 {
+            if field.sheep_won() || field.dog_won() {
+                field = start.clone();
+            }
+            let dog_states = field.get_dog_states();
+            field = dog_states[rng.gen_range(0..dog_states.len())].clone();
+            field.move_sheep(&policy, &mut rng);
+            for (index, sheep) in field.flock.iter().enumerate() {
+                assert!(!field.flock[..index].contains(sheep), "{:?}", field.flock);
+            }
+        }
+    }
+
+    // the simulator draws from the same distribution the solver backs up
+    #[test]
+    fn simulated_reactions_follow_sheep_transitions() {
+        let config = FieldConfig::new(7);
+        let policy = Lazy::new(Box::new(Flee::default()), 0.25);
+        let field = Field::with(&config, Sheep::at(1, 1), Dog::at(2, 3));
+        let outcomes = field.sheep_transitions(0, &policy);
+        assert!(outcomes.len() > 2);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let draws = 40000;
+        let mut counts = vec![0; outcomes.len()];
+        for _ in 0..draws {
+            let mut next = field.clone();
+            next.move_sheep(&policy, &mut rng);
+            let position = outcomes
+                .iter()
+                .position(|(outcome, _)| *outcome == next)
+                .expect("the simulator reached an outcome the solver doesn't know about");
+            counts[position] += 1;
+        }
+        for ((_, probability), count) in outcomes.iter().zip(counts) {
+            let frequency = count as f32 / draws as f32;
+            assert!(
+                (frequency - probability).abs() < 0.01,
+                "{} against {}",
+                frequency,
+                probability
+            );
+        }
+    }
+}
@@ -1,33 +1,71 @@
 mod data;
+mod deadline;
+mod dog_policy;
 mod field;
+mod map_file;
 mod math;
+mod mdp;
+mod move_set;
+mod pen;
+mod policy_table;
+mod reachability;
+mod sheep_policy;
 mod simulations;
 mod solve_markov;
+mod terrain;
+mod utility_table;
 
-#[macro_use]
-extern crate queues;
 use std::collections::HashMap;
-use std::hash::Hash;
+use std::thread;
+use std::time::Instant;
 
-use crate::data::{load_partitioned_data, load_distance_data, save_distance_data};
-use crate::field::{Dog, Field, Sheep};
+use crate::data::PartitionedData;
+use crate::deadline::solve_deadline;
+use crate::dog_policy::{
+    average_from_start, evaluate_dog_policy, steps_to_pen, uniform_starts, DogPolicy, Lure,
+    ModelGreedy,
+};
+use crate::field::{Dog, Field, FieldConfig, Sheep};
 use crate::math::bfs_sheep;
+use crate::mdp::{policy_iteration, value_iteration, Evaluation, SolverOptions, Sweep};
+use crate::move_set::MoveSet;
+use crate::pen::{Pen, PenShape, Side};
+use crate::policy_table::PolicyTable;
+use crate::reachability::analyze;
+use crate::sheep_policy::{EuclideanFlee, Flee, Lazy, RandomWalk, SheepPolicy};
+use crate::simulations::find_best_starting_location;
+use crate::simulations::run_deadline_simulation;
+use crate::simulations::run_flock_simulation;
+use crate::simulations::run_pack_simulation;
+use crate::simulations::run_policy_simulation;
 use crate::simulations::run_simulation_with_model;
+use crate::solve_markov::dog_move_values;
+use crate::solve_markov::generate_adversarial_utility;
+use crate::solve_markov::generate_dog_policy;
+use crate::solve_markov::generate_flock_utility;
+use crate::solve_markov::generate_forced_times;
+use crate::solve_markov::generate_optimal_utlility;
+use crate::solve_markov::generate_pack_utility;
+use crate::solve_markov::generate_reachable_utility;
+use crate::solve_markov::HerdingGame;
+use crate::utility_table::UtilityTable;
+use data::load_field;
+use data::load_partitioned_data;
+use data::load_policy_table;
 use data::load_utility_map;
-use math::{
-    dot_product, loss, model_1, model_2, scalar_multiple, vector_subtraction, weighted_loss, bfs_dog,
-};
+use data::save_field;
+use data::save_partitioned_data;
+use math::{dot_product, model_2, scalar_multiple, vector_subtraction, weighted_loss};
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
 
 fn stochastic_gradient_descent(
-    data: (
-        Vec<((Sheep, Dog), f32)>,
-        Vec<((Sheep, Dog), f32)>,
-        Vec<((Sheep, Dog), f32)>,
-    ),
+    data: PartitionedData,
+    config: &FieldConfig,
+    rng: &mut impl Rng,
 ) -> (f32, f32, f32, f32, f32) {
-    let distance_map_sheep = make_distance_map_sheep();
+    let distance_map_sheep = make_distance_map_sheep(config);
     println!("loaded the distance maps");
     let training_data = data.0.clone();
     let learning_rate = 0.0000003;
@@ -37,7 +75,6 @@ fn stochastic_gradient_descent(
         Err(_) => return (0.0, 0.0, 0.0, 0.0, 0.0),
     };
 
-    let mut rng = rand::thread_rng();
     let w0 = (
         rng.gen::<f32>() / 10.0,
         rng.gen::<f32>() / 10.0,
@@ -46,20 +83,20 @@ fn stochastic_gradient_descent(
         rng.gen::<f32>() / 10.0,
     );
 
-    let mut w_k = w0.clone();
+    let mut w_k = w0;
     let unit_vector = (1.0, 1.0, 1.0, 1.0, 1.0);
-    let mut loss_value = weighted_loss(&data.0, w_k);
+    let mut loss_value = weighted_loss(&data.0, w_k, config);
     let mut best_vector = unit_vector;
     for iteration in 0..10000 {
-        let data_point = training_data.choose(&mut rng).unwrap();
-        let data_vector = model_2(data_point.0, &distance_map_sheep);
+        let data_point = training_data.choose(rng).unwrap();
+        let data_vector = model_2(data_point.0, &distance_map_sheep, config);
         let test_value = dot_product(data_vector, w_k);
         let difference = test_value - data_point.1;
         let difference_vector =
             scalar_multiple(scalar_multiple(data_vector, difference), learning_rate);
         let new_vector = vector_subtraction(difference_vector, w_k);
 
-        let new_vector_loss_testing = weighted_loss(&data.1, new_vector);
+        let new_vector_loss_testing = weighted_loss(&data.1, new_vector, config);
 
         let _result = wtr.write_record(&[
             format!("{}", iteration),
@@ -71,9 +108,9 @@ fn stochastic_gradient_descent(
             loss_value = new_vector_loss_testing;
             best_vector = new_vector;
         }
-        if dot_product(unit_vector, new_vector) > 1000.0 {
-            w_k = best_vector;
-        } else if new_vector_loss_testing > 10.0 + loss_value {
+        if dot_product(unit_vector, new_vector) > 1000.0
+            || new_vector_loss_testing > 10.0 + loss_value
+        {
             w_k = best_vector;
         } else {
             w_k = new_vector;
@@ -85,78 +122,748 @@ fn stochastic_gradient_descent(
     best_vector
 }
 
-fn make_distance_map_sheep() -> HashMap<Sheep, f32> {
+fn make_distance_map_sheep(config: &FieldConfig) -> HashMap<Sheep, f32> {
     let mut result = HashMap::new();
-    for row in 0..31 {
-        for column in 0..31 {
+    for row in 0..config.size {
+        for column in 0..config.size {
             let sheep = Sheep::at(column, row);
             let position = if row == 0 && column == 0 { 1 } else { 0 };
             let dog = Dog::at(position, position);
-            result.insert(sheep, bfs_sheep((sheep, dog)));
+            result.insert(sheep, bfs_sheep((sheep, dog), config));
         }
     }
     result
 }
 
-fn make_distance_map_dog() -> HashMap<(Sheep, Dog), f32> {
-    let mut result = HashMap::new();
-    for row in 0..31 {
-        for column in 0..31 {
-            let dog = Dog::at(column, row);
-            for row in 0..31 {
-                for column in 0..31 {
-                    let sheep = Sheep::at(row, column);
-                    result.insert((sheep, dog), bfs_dog((sheep, dog)));
-                }
-            }
-        }
-    }
-    result
-}
-
-fn run_simulations(model: (f32, f32, f32, f32, f32), map: HashMap<(Sheep, Dog), f32>) {
+fn run_simulations(
+    model: (f32, f32, f32, f32, f32),
+    map: UtilityTable,
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    rng: &mut impl Rng,
+) {
     let mut average = 0.0;
     let mut games_won = 0.0;
     let mut games_expired = 0.0;
-    let distance_map_sheep = make_distance_map_sheep();
+    let distance_map_sheep = make_distance_map_sheep(config);
 
-    for _ in 0..1000 {
-        let (difference, game_won) = run_simulation_with_model(model, &map, &distance_map_sheep);
+    let games = 1000;
+    for _ in 0..games {
+        let (difference, game_won) =
+            run_simulation_with_model(model, &map, &distance_map_sheep, config, policy, rng);
         if game_won {
-            average = average + difference;
-            games_won = games_won + 1.0;
+            average += difference;
+            games_won += 1.0;
         }
         if !game_won && difference == 251.0 {
-            games_expired = games_expired + 1.0;
+            games_expired += 1.0;
         }
     }
-    average = average / games_won;
+    average /= games_won;
     println!(
         "{} across {} games. {} games expired. {} lost",
         average,
         games_won,
         games_expired,
-        10.0 - games_won - games_expired
+        games as f32 - games_won - games_expired
+    );
+}
+
+// how far the dog's actual herding time is from the expected one when it just follows the policy
+// table
+fn run_policy_simulations(
+    dog_policy: &PolicyTable,
+    map: &UtilityTable,
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    rng: &mut impl Rng,
+) {
+    let games = 1000;
+    let mut average = 0.0;
+    for _ in 0..games {
+        average += run_policy_simulation(dog_policy, map, config, policy, rng);
+    }
+    average /= games as f32;
+    println!("{} moves off the expected time across {} games", average, games);
+}
+
+// an average from average_from_start, or that there was nothing to average
+fn print_average(name: &str, average: Option<f32>) {
+    match average {
+        Some(moves) => println!("{}: {} expected moves across the sheep starts", name, moves),
+        None => println!(
+            "{}: no sheep start is sure to end with the sheep penned",
+            name
+        ),
+    }
+}
+
+// how much each extra dog cuts the expected herding time, averaged over every sheep start, and
+// how far games played on the joint map land from it
+fn compare_pack_sizes(
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    options: &SolverOptions,
+    largest_pack: usize,
+    games: usize,
+    rng: &mut impl Rng,
+) {
+    for pack_size in 1..=largest_pack {
+        let (map, report) = generate_pack_utility(config, policy, pack_size, options);
+        println!("{} dogs: {:?}", pack_size, report);
+        let mut dogs = config.pack_start(pack_size);
+        dogs.sort();
+        let (average, _) = average_from_start(config, &dogs, |sheep| {
+            map.get(&(sheep, dogs.clone())).copied()
+        });
+        print_average(&format!("{} dogs", pack_size), average);
+        let mut total = 0.0;
+        let mut won = 0;
+        for _ in 0..games {
+            let (difference, dog_won) = run_pack_simulation(&map, config, policy, pack_size, rng);
+            if dog_won {
+                total += difference;
+                won += 1;
+            }
+        }
+        println!(
+            "{} dogs: {} moves off the expected time across the {} of {} games won",
+            pack_size,
+            total / won as f32,
+            won,
+            games
+        );
+    }
+}
+
+// herding a flock by steering on the single sheep map, against solving the joint flock game,
+// which only fits on small fields. the pen is widened to a cup with room for the whole flock
+fn compare_flock_sizes(
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    options: &SolverOptions,
+    largest_flock: usize,
+    games: usize,
+    rng: &mut impl Rng,
+) {
+    let middle = config.size / 2;
+    let pen = Pen::new(
+        (middle, middle),
+        PenShape::Cup {
+            width: largest_flock as i32,
+            depth: 1,
+        },
+        Side::Up,
+    );
+    let config = config.clone().with_pen(pen);
+    let (map, _) = generate_optimal_utlility(&config, policy, options);
+    for flock_size in 1..=largest_flock {
+        let (joint, report) = generate_flock_utility(&config, policy, flock_size, options);
+        println!("{} sheep: {:?}", flock_size, report);
+        let from_start: Vec<f32> = joint
+            .iter()
+            .filter(|((flock, dog), _)| {
+                // leave out a sheep starting on top of the dog
+                *dog == config.dog_start
+                    && !Field::with_flock(&config, flock.clone(), *dog).sheep_won()
+            })
+            .map(|(_, expected_moves)| *expected_moves)
+            .collect();
+        println!(
+            "{} sheep: {} expected moves across {} starts playing the joint game",
+            flock_size,
+            from_start.iter().sum::<f32>() / from_start.len() as f32,
+            from_start.len()
+        );
+        let mut total = 0.0;
+        let mut won = 0;
+        for _ in 0..games {
+            let (moves, dog_won) = run_flock_simulation(&map, &config, policy, flock_size, rng);
+            if dog_won {
+                total += moves;
+                won += 1;
+            }
+        }
+        println!(
+            "{} sheep: {} moves on average steering on the single sheep map, {} of {} games won",
+            flock_size,
+            total / won as f32,
+            won,
+            games
+        );
+    }
+}
+
+// expected herding time from the usual dog start, averaged over every sheep start, for each of
+// the sheep's policies
+fn compare_sheep_policies(config: &FieldConfig, options: &SolverOptions) {
+    let policies: [Box<dyn SheepPolicy>; 4] = [
+        Box::new(Flee::default()),
+        Box::new(EuclideanFlee::default()),
+        Box::new(RandomWalk),
+        Box::new(Lazy::new(Box::new(Flee::default()), 0.25)),
+    ];
+    for policy in policies {
+        let (map, report) = generate_optimal_utlility(config, policy.as_ref(), options);
+        println!("{}: {:?}", policy.name(), report);
+        let dog = config.dog_start;
+        let (average, _) = average_from_start(config, &[dog], |sheep| map.get((sheep, dog)));
+        print_average(&policy.name(), average);
+    }
+}
+
+// expected herding time from the usual dog start, averaged over every sheep start, for a few
+// pairings of dog and sheep movement
+fn compare_move_sets(config: &FieldConfig, policy: &dyn SheepPolicy, options: &SolverOptions) {
+    let pairings = [
+        ("usual", MoveSet::moore(), MoveSet::von_neumann()),
+        ("fast dog", MoveSet::moore().with_speed(2), MoveSet::von_neumann()),
+        ("diagonal sheep", MoveSet::moore(), MoveSet::moore()),
+        ("rook dog", MoveSet::von_neumann(), MoveSet::von_neumann()),
+        (
+            "both can wait",
+            MoveSet::moore().with_wait(),
+            MoveSet::von_neumann().with_wait(),
+        ),
+    ];
+    for (name, dog_moves, sheep_moves) in pairings {
+        let config = config
+            .clone()
+            .with_dog_moves(dog_moves)
+            .with_sheep_moves(sheep_moves);
+        let (map, report) = generate_optimal_utlility(&config, policy, options);
+        println!("{}: {:?}", name, report);
+        let dog = config.dog_start;
+        let (average, _) = average_from_start(&config, &[dog], |sheep| map.get((sheep, dog)));
+        print_average(name, average);
+    }
+}
+
+// solve the same field with every sweep of value iteration and both flavours of policy iteration,
+// timing each and checking how far their answers end up from the plain sweep. exact evaluation is
+// only run on small fields
+fn compare_solvers(config: &FieldConfig, policy: &dyn SheepPolicy) {
+    let game = HerdingGame::new(config, policy);
+
+    let options = SolverOptions::default();
+    let (value_iteration_map, report) = value_iteration(&game, &options);
+    println!("value iteration with Jacobi sweeps: {:?}", report);
+
+    for sweep in [Sweep::GaussSeidel, Sweep::Prioritized] {
+        let options = SolverOptions {
+            sweep,
+            ..SolverOptions::default()
+        };
+        let (map, report) = value_iteration(&game, &options);
+        let largest_difference = map
+            .iter()
+            .map(|(state, value)| (value - value_iteration_map.get(state).unwrap()).abs())
+            .fold(0.0, f32::max);
+        println!(
+            "value iteration with {:?} sweeps: {:?}, values at most {} from Jacobi",
+            sweep, report, largest_difference
+        );
+    }
+
+    let mut evaluations = vec![Evaluation::Iterative { tolerance: 0.001 }];
+    if value_iteration_map.len() <= 5000 {
+        evaluations.push(Evaluation::Exact);
+    }
+    for evaluation in evaluations {
+        let start = Instant::now();
+        let (map, report) = policy_iteration(&game, evaluation, &options);
+        let elapsed = start.elapsed();
+        let largest_difference = map
+            .iter()
+            .map(|(state, value)| (value - value_iteration_map.get(state).unwrap()).abs())
+            .fold(0.0, f32::max);
+        println!(
+            "policy iteration with {:?} evaluation took {:?}: {} improvement steps, {} evaluation sweeps, converged {}, values at most {} from value iteration",
+            evaluation,
+            elapsed,
+            report.improvement_steps,
+            report.evaluation_sweeps,
+            report.converged,
+            largest_difference
+        );
+    }
+}
+
+// the exact expected moves to pen the sheep from the usual dog start for the solved policy, the
+// learned model played greedily and a rule of thumb, with no simulation noise
+fn compare_dog_policies(
+    model: (f32, f32, f32, f32, f32),
+    map: &UtilityTable,
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    options: &SolverOptions,
+) {
+    let table = generate_dog_policy(config, policy, map, options);
+    let model_greedy = ModelGreedy {
+        model,
+        distances: make_distance_map_sheep(config),
+        sheep_policy: policy,
+    };
+    let lure = Lure {
+        sheep_policy: policy,
+    };
+    let dog_policies: [(&str, &dyn DogPolicy); 3] = [
+        ("optimal", &table),
+        ("model greedy", &model_greedy),
+        ("lure", &lure),
+    ];
+    for (name, dog_policy) in dog_policies {
+        let (moves, report) = evaluate_dog_policy(config, policy, dog_policy);
+        let dog = config.dog_start;
+        let (average, improper) =
+            average_from_start(config, &[dog], |sheep| moves.get((sheep, dog)));
+        print_average(name, average);
+        println!(
+            "{}: {} sheep starts it can fail from, each value within {} moves",
+            name, improper, report.error_bound
+        );
+    }
+}
+
+// how much worse herding gets when the sheep picks the worst of its moves for the dog instead of
+// one at random, averaged over the sheep starts, and from how many starts the dog can still force
+// a pen
+fn compare_opponents(config: &FieldConfig, policy: &dyn SheepPolicy, options: &SolverOptions) {
+    let (random, report) = generate_optimal_utlility(config, policy, options);
+    println!("against a random sheep: {:?}", report);
+    let (adversarial, report) = generate_adversarial_utility(config, policy, options);
+    println!("against the worst sheep: {:?}", report);
+    let forced = generate_forced_times(config, policy);
+    let dog = config.dog_start;
+    let mut random_total = 0.0;
+    let mut adversarial_total = 0.0;
+    let mut forced_total = 0.0;
+    let mut forced_starts = 0;
+    let mut starts = 0;
+    for y in 0..config.size {
+        for x in 0..config.size {
+            let state = (Sheep::at(x, y), dog);
+            // leave out the sheep starting on top of the dog
+            if (x, y) == (dog.x, dog.y) || !random.contains(state) {
+                continue;
+            }
+            starts += 1;
+            random_total += random.get(state).unwrap();
+            adversarial_total += adversarial.get(state).unwrap();
+            let time = forced.get(state).unwrap();
+            if time.is_finite() {
+                forced_total += time;
+                forced_starts += 1;
+            }
+        }
+    }
+    println!(
+        "across {} sheep starts: {} expected against a random sheep, {} against the worst sheep",
+        starts,
+        random_total / starts as f32,
+        adversarial_total / starts as f32
+    );
+    println!(
+        "the dog can force a pen from {} of them, in {} moves on average",
+        forced_starts,
+        forced_total / forced_starts as f32
     );
 }
 
+// the tail of the herding time under the solved policy, over every sheep start with the dog at its
+// usual start
+fn print_steps_to_pen(
+    map: &UtilityTable,
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    options: &SolverOptions,
+) {
+    let table = generate_dog_policy(config, policy, map, options);
+    let steps = steps_to_pen(config, policy, &table, &uniform_starts(config), 200);
+    for chance in [0.5, 0.9, 0.99] {
+        match steps.percentile(chance) {
+            Some(moves) => println!("penned within {} moves {}% of the time", moves, chance * 100.0),
+            None => println!("not penned {}% of the time within 200 moves", chance * 100.0),
+        }
+    }
+    for moves in [5, 10, 20, 50] {
+        println!("penned within {} moves: {}", moves, steps.penned[moves]);
+    }
+    println!(
+        "run into the dog: {}, still going after 200 moves: {}",
+        steps.lost[200],
+        steps.unfinished()
+    );
+}
+
+// the chance of penning the sheep by each deadline, for the policy that only cares about the
+// deadline against the one that minimizes the expected time, over every sheep start with the dog
+// at its usual start. then games against the longest deadline, as a check on the solve
+fn compare_deadlines(
+    map: &UtilityTable,
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    options: &SolverOptions,
+    deadlines: &[usize],
+    games: usize,
+    rng: &mut impl Rng,
+) {
+    let table = generate_dog_policy(config, policy, map, options);
+    let starts = uniform_starts(config);
+    let longest = deadlines.iter().copied().max().unwrap_or(0);
+    let stationary = steps_to_pen(config, policy, &table, &starts, longest);
+    let mut in_time = vec![0.0f64; longest + 1];
+    let (_, deadline_policy) = solve_deadline(config, policy, longest, |moves_left, chances| {
+        in_time[moves_left] = starts
+            .iter()
+            .map(|(state, chance)| *chance as f64 * chances.get(*state).unwrap() as f64)
+            .sum();
+    });
+    for deadline in deadlines {
+        println!(
+            "within {} moves: {} playing for the deadline, {} playing for the expected time",
+            deadline, in_time[*deadline], stationary.penned[*deadline]
+        );
+    }
+    let penned = (0..games)
+        .filter(|_| run_deadline_simulation(&deadline_policy, config, policy, rng))
+        .count();
+    println!(
+        "penned within {} moves in {} of {} simulated games",
+        longest, penned, games
+    );
+}
+
+// every move the dog has on a field, cheapest first, with where the sheep might go after each
+fn explain_dog_moves(
+    field: &Field,
+    map: &UtilityTable,
+    policy: &dyn SheepPolicy,
+    options: &SolverOptions,
+) {
+    field.print();
+    let mut moves = dog_move_values(field, map, policy, options);
+    moves.sort_by(|one, two| one.expected_cost.total_cmp(&two.expected_cost));
+    for dog_move in moves {
+        let reactions: Vec<String> = dog_move
+            .reactions
+            .iter()
+            .map(|(sheep, probability)| format!("({}, {}) {}", sheep.x, sheep.y, probability))
+            .collect();
+        println!(
+            "dog to ({}, {}): {} expected, sheep to {}",
+            dog_move.dog.x,
+            dog_move.dog.y,
+            dog_move.expected_cost,
+            reactions.join(", ")
+        );
+    }
+}
+
+// how much of the state space games from the usual dog start actually use, where the sheep can
+// end up somewhere the dog can never pen it from, and how much solving only the reachable states
+// saves
+fn analyze_state_space(config: &FieldConfig, policy: &dyn SheepPolicy, options: &SolverOptions) {
+    let game = HerdingGame::new(config, policy);
+    let analysis = analyze(&game, &game.starts());
+    println!(
+        "{} reachable states, {} unreachable. {} terminal, {} absorbing, {} traps",
+        analysis.reachable.len(),
+        analysis.unreachable,
+        analysis.terminal.len(),
+        analysis.absorbing.len(),
+        analysis.traps.len()
+    );
+    for (sheep, dog) in analysis.traps.iter().take(10) {
+        println!("trap: sheep at ({}, {}), dog at ({}, {})", sheep.x, sheep.y, dog.x, dog.y);
+    }
+    let (_, report) = generate_optimal_utlility(config, policy, options);
+    println!("solving every state: {:?}", report);
+    let (_, report) = generate_reachable_utility(config, policy, options);
+    println!("solving the reachable states: {:?}", report);
+}
+
+// fit the linear model to the solved utilities, splitting them into training, testing and
+// validation data the first time and reusing that split after
+fn train_model(
+    path: &str,
+    map: &UtilityTable,
+    config: &FieldConfig,
+    rng: &mut impl Rng,
+) -> (f32, f32, f32, f32, f32) {
+    let partitioned_data = load_partitioned_data(path, map, config, rng);
+    if let Err(e) = save_partitioned_data(path, partitioned_data.clone()) {
+        println!("could not save the partitioned data: {}", e);
+    }
+    let model = stochastic_gradient_descent(partitioned_data, config, rng);
+    println!("{:?}", model);
+    model
+}
+
+// write the field with the dog wherever it does best on average over the sheep starts, as a map
+// file to start other experiments from
+fn save_best_start(path: &str, map: &UtilityTable, config: &FieldConfig) {
+    let field = find_best_starting_location(map, config);
+    field.print();
+    if let Err(e) = save_field(path, &field) {
+        println!("could not save the field: {}", e);
+    }
+}
+
+// the model fitted last, avg error 17.5 moves on the 31x31 field
+const MODEL: (f32, f32, f32, f32, f32) = (0.5948616, 0.62768173, 0.07846236, 0.4726258, 0.0911483);
+
+const EXPERIMENTS: [(&str, &str); 15] = [
+    ("model", "play games steering by the fitted linear model"),
+    ("policy", "play games following the solved policy table"),
+    ("train", "fit the linear model to the solved utilities"),
+    (
+        "solvers",
+        "solve with every solver and compare their answers",
+    ),
+    (
+        "dog-policies",
+        "evaluate the solved policy, the model and a rule of thumb",
+    ),
+    (
+        "opponents",
+        "compare a random sheep with the worst sheep for the dog",
+    ),
+    (
+        "steps",
+        "print how long the solved policy takes to pen the sheep",
+    ),
+    (
+        "deadlines",
+        "play for a deadline against playing for the expected time",
+    ),
+    (
+        "explain",
+        "list the dog's moves on the --field map file, or the start",
+    ),
+    (
+        "best-start",
+        "save the best dog start as a map file at --field",
+    ),
+    (
+        "states",
+        "analyze which states games from the start can reach",
+    ),
+    ("packs", "compare packs of one and two dogs"),
+    ("flocks", "compare flocks of one and two sheep"),
+    ("sheep-policies", "compare the sheep's policies"),
+    (
+        "move-sets",
+        "compare ways for the dog and the sheep to move",
+    ),
+];
+
+// what to run, and on what: project3 [experiment] [--size N] [--seed N] [--field PATH]
+#[derive(Debug, PartialEq)]
+struct Args {
+    experiment: String,
+    size: i32,
+    seed: u64,
+    field: Option<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        experiment: "model".to_string(),
+        size: 31,
+        seed: 0,
+        field: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        match arg.as_str() {
+            "--size" => {
+                let size = value("--size")?;
+                parsed.size = size
+                    .parse()
+                    .ok()
+                    .filter(|size| *size >= 5 && size % 2 == 1)
+                    .ok_or(format!(
+                        "the size has to be odd and at least 5, not {}",
+                        size
+                    ))?;
+            }
+            "--seed" => {
+                let seed = value("--seed")?;
+                parsed.seed = seed
+                    .parse()
+                    .map_err(|_| format!("the seed has to be a number, not {}", seed))?;
+            }
+            "--field" => parsed.field = Some(value("--field")?),
+            experiment if EXPERIMENTS.iter().any(|(name, _)| *name == experiment) => {
+                parsed.experiment = experiment.to_string()
+            }
+            other => return Err(format!("unknown argument {}", other)),
+        }
+    }
+    Ok(parsed)
+}
+
+fn usage() -> String {
+    let mut text =
+        "usage: project3 [experiment] [--size N] [--seed N] [--field PATH]\n\nexperiments, model by default:\n"
+            .to_string();
+    for (name, description) in EXPERIMENTS {
+        text += &format!("  {:<16}{}\n", name, description);
+    }
+    text
+}
+
+fn run(args: &Args) {
+    let config = FieldConfig::new(args.size);
+    let policy = Flee::default();
+    let options = SolverOptions {
+        threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+        ..SolverOptions::default()
+    };
+    // every random choice in an experiment comes from this one generator, so a run can be
+    // repeated exactly by reusing its seed
+    let mut rng = StdRng::seed_from_u64(args.seed);
+    println!("seed {}", args.seed);
+    let name = format!("cached_utlity_map_{}x{}", config.size, config.size);
+    let policy_name = format!("cached_dog_policy_{}x{}", config.size, config.size);
+    let data_file = format!("partitioned_data_{}x{}", config.size, config.size);
+    let load_map = || load_utility_map(&name, &config, &policy, &options);
+
+    match args.experiment.as_str() {
+        "model" => run_simulations(MODEL, load_map(), &config, &policy, &mut rng),
+        "policy" => {
+            let map = load_map();
+            let table = load_policy_table(&policy_name, &map, &config, &policy, &options);
+            run_policy_simulations(&table, &map, &config, &policy, &mut rng);
+        }
+        "train" => {
+            train_model(&data_file, &load_map(), &config, &mut rng);
+        }
+        "solvers" => compare_solvers(&config, &policy),
+        "dog-policies" => compare_dog_policies(MODEL, &load_map(), &config, &policy, &options),
+        "opponents" => compare_opponents(&config, &policy, &options),
+        "steps" => print_steps_to_pen(&load_map(), &config, &policy, &options),
+        "deadlines" => compare_deadlines(
+            &load_map(),
+            &config,
+            &policy,
+            &options,
+            &[10, 20, 50],
+            1000,
+            &mut rng,
+        ),
+        "explain" => {
+            let field = match &args.field {
+                Some(path) => match load_field(path) {
+                    Ok(field) => field,
+                    Err(e) => return println!("could not read the map file: {}", e),
+                },
+                None => Field::new(&config),
+            };
+            let size = field.config().size;
+            let name = format!("cached_utlity_map_{}x{}", size, size);
+            let map = load_utility_map(&name, field.config(), &policy, &options);
+            explain_dog_moves(&field, &map, &policy, &options);
+        }
+        "best-start" => {
+            let default_path = format!("best_start_{}x{}", config.size, config.size);
+            let path = args.field.as_ref().unwrap_or(&default_path);
+            save_best_start(path, &load_map(), &config);
+        }
+        "states" => analyze_state_space(&config, &policy, &options),
+        "packs" => compare_pack_sizes(&config, &policy, &options, 2, 100, &mut rng),
+        "flocks" => compare_flock_sizes(&config, &policy, &options, 2, 100, &mut rng),
+        "sheep-policies" => compare_sheep_policies(&config, &options),
+        "move-sets" => compare_move_sets(&config, &policy, &options),
+        other => unreachable!("{} is not an experiment", other),
+    }
+}
+
 fn main() {
-    let name = "cached_utlity_map_31x31";
-    let data_file = "partitioned_data";
-    let map = load_utility_map(name);
-    // let partitioned_data = load_partitioned_data(data_file, &map);
-    println!("loaded the map");
-
-    // let best_model = stochastic_gradient_descent(partitioned_data);
-    // println!("{:?}", best_model);
-
-
-    // let model = stochastic_gradient_descent(partitioned_data);
-    let model_latest_try = (0.5948616, 0.62768173, 0.07846236, 0.4726258, 0.0911483);
-    // let best_model2 = (0.33271807, 0.8405044, 0.022575932, 0.52544063, 0.09900899);
-    // let best_model = (0.66582894, 0.6932436, 0.056437638, 0.3580382, 0.04303138); // avg error 17.512537
-    // let model_2_trial2 = (0.5116242, 0.68731534, 0.09846029, 0.44221365, 0.0347358);
-    run_simulations(model_latest_try, map);
-    // let _result = save_partitioned_data(data_file, partitioned_data);
-    // let _result = save_utility_map(name, map);
+    match parse_args(std::env::args().skip(1)) {
+        Ok(args) => run(&args),
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, usage());
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn arguments_pick_the_experiment_and_the_field() {
+        let defaults = Args {
+            experiment: "model".to_string(),
+            size: 31,
+            seed: 0,
+            field: None,
+        };
+        assert_eq!(parse(&[]), Ok(defaults));
+        let args = parse(&["--size", "9", "policy", "--seed", "7", "--field", "start"]).unwrap();
+        assert_eq!(
+            args,
+            Args {
+                experiment: "policy".to_string(),
+                size: 9,
+                seed: 7,
+                field: Some("start".to_string()),
+            }
+        );
+        assert!(parse(&["--size", "8"]).is_err());
+        assert!(parse(&["--size", "3"]).is_err());
+        assert!(parse(&["--seed"]).is_err());
+        assert!(parse(&["herd"]).is_err());
+    }
+
+    // the whole pipeline on a field the cached maps weren't solved for: solve and save the map,
+    // read it back, pull the policy out, play it and start again from the best dog start
+    #[test]
+    fn an_odd_field_other_than_31_runs_end_to_end() {
+        let scratch = |name: &str| {
+            std::env::temp_dir().join(format!(
+                "project3_end_to_end_{}_{}",
+                name,
+                std::process::id()
+            ))
+        };
+        let (map_path, policy_path, field_path) =
+            (scratch("map"), scratch("policy"), scratch("field"));
+        let config = FieldConfig::new(9);
+        let policy = Flee::default();
+        let options = SolverOptions::default();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let map = load_utility_map(&map_path, &config, &policy, &options);
+        assert!(load_utility_map(&map_path, &config, &policy, &options) == map);
+        let table = load_policy_table(&policy_path, &map, &config, &policy, &options);
+        assert!(load_policy_table(&policy_path, &map, &config, &policy, &options) == table);
+
+        let (moves, _) = evaluate_dog_policy(&config, &policy, &table);
+        let dog = config.dog_start;
+        let (average, improper) =
+            average_from_start(&config, &[dog], |sheep| moves.get((sheep, dog)));
+        assert!(average.unwrap() > 0.0);
+        assert_eq!(improper, 0);
+        for _ in 0..50 {
+            let off = run_policy_simulation(&table, &map, &config, &policy, &mut rng);
+            assert!(off.is_finite());
+        }
+
+        save_best_start(field_path.to_str().unwrap(), &map, &config);
+        let field = load_field(&field_path).unwrap();
+        assert_eq!(field.config().size, 9);
+        assert!(!dog_move_values(&field, &map, &policy, &options).is_empty());
+
+        for path in [map_path, policy_path, field_path] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
 }
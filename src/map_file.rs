@@ -0,0 +1,202 @@
+// plain text maps, one token per cell, in the same layout `Field::print` uses:
+//   _  empty          ☐  pen fence      #  obstacle
+//   S  sheep          D  dog            G  pen goal
+//   s  sheep on goal  d  dog on goal
+//   X  sheep run into a dog, x the same on the goal
+// a flock or a pack is written as several sheep or dogs
+use crate::field::Dog;
+use crate::field::Entity;
+use crate::field::Field;
+use crate::field::FieldConfig;
+use crate::field::Sheep;
+use crate::pen::Pen;
+use crate::pen::PenShape;
+use crate::pen::Side;
+
+pub(crate) fn format_field(field: &Field) -> String {
+    let goal = field.config().pen.goal;
+    let size = field.config().size;
+    let mut result = String::new();
+    for y in 0..size {
+        for x in 0..size {
+            let on_goal = (x, y) == goal;
+            let caught = field.flock.iter().any(|sheep| (sheep.x, sheep.y) == (x, y))
+                && field.dogs.iter().any(|dog| (dog.x, dog.y) == (x, y));
+            let token = match field.entity_at(x, y).unwrap() {
+                Entity::Dog if caught && on_goal => "x",
+                Entity::Dog if caught => "X",
+                Entity::Empty if on_goal => "G",
+                Entity::Empty => "_",
+                Entity::Fence => "☐",
+                Entity::Obstacle => "#",
+                Entity::Sheep if on_goal => "s",
+                Entity::Sheep => "S",
+                Entity::Dog if on_goal => "d",
+                Entity::Dog => "D",
+            };
+            result.push_str(token);
+            result.push(' ');
+        }
+        result.push('\n');
+    }
+    result
+}
+
+pub(crate) fn parse_field(text: &str) -> Result<Field, String> {
+    let rows: Vec<Vec<char>> = text
+        .lines()
+        .map(|line| {
+            line.chars()
+                .filter(|c| !c.is_whitespace())
+                .collect::<Vec<char>>()
+        })
+        .filter(|row| !row.is_empty())
+        .collect();
+
+    let size = rows.len() as i32;
+    if size < 5 || size % 2 == 0 {
+        return Err(format!(
+            "map must have an odd number of rows, at least 5, got {}",
+            size
+        ));
+    }
+
+    let mut fences = Vec::new();
+    let mut obstacles = Vec::new();
+    let mut goals = Vec::new();
+    let mut sheep = Vec::new();
+    let mut dogs = Vec::new();
+    for (y, row) in rows.iter().enumerate() {
+        if row.len() as i32 != size {
+            return Err(format!(
+                "row {} has {} cells but the map is {} rows tall",
+                y,
+                row.len(),
+                size
+            ));
+        }
+        for (x, token) in row.iter().enumerate() {
+            let position = (x as i32, y as i32);
+            match token {
+                '_' => (),
+                '☐' => fences.push(position),
+                '#' => obstacles.push(position),
+                'G' => goals.push(position),
+                'S' => sheep.push(Sheep::at(position.0, position.1)),
+                'D' => dogs.push(Dog::at(position.0, position.1)),
+                's' => {
+                    goals.push(position);
+                    sheep.push(Sheep::at(position.0, position.1));
+                }
+                'd' => {
+                    goals.push(position);
+                    dogs.push(Dog::at(position.0, position.1));
+                }
+                'X' => {
+                    sheep.push(Sheep::at(position.0, position.1));
+                    dogs.push(Dog::at(position.0, position.1));
+                }
+                'x' => {
+                    goals.push(position);
+                    sheep.push(Sheep::at(position.0, position.1));
+                    dogs.push(Dog::at(position.0, position.1));
+                }
+                other => return Err(format!("unknown map token '{}' at ({}, {})", other, x, y)),
+            }
+        }
+    }
+
+    let goal = match goals[..] {
+        [goal] => goal,
+        _ => {
+            return Err(format!(
+                "map needs exactly one goal cell, found {}",
+                goals.len()
+            ))
+        }
+    };
+    if sheep.is_empty() {
+        return Err("map needs at least one sheep".to_string());
+    }
+    if dogs.is_empty() {
+        return Err("map needs at least one dog".to_string());
+    }
+    if fences.is_empty() {
+        return Err("map has no pen fences".to_string());
+    }
+
+    let offsets = fences
+        .iter()
+        .map(|(x, y)| (x - goal.0, y - goal.1))
+        .collect();
+    let pen = Pen::new(goal, PenShape::Custom(offsets), opening_side(&fences)?);
+
+    let mut config = FieldConfig::new(size)
+        .with_pen(pen)
+        .with_obstacles(obstacles);
+    config.sheep_start = sheep[0];
+    config.dog_start = dogs[0];
+    Ok(Field::with_pack(&config, sheep, dogs))
+}
+
+// the pen opens on the one edge of its bounding box that is not fenced all the way along
+fn opening_side(fences: &[(i32, i32)]) -> Result<Side, String> {
+    let left = fences.iter().map(|fence| fence.0).min().unwrap();
+    let right = fences.iter().map(|fence| fence.0).max().unwrap();
+    let top = fences.iter().map(|fence| fence.1).min().unwrap();
+    let bottom = fences.iter().map(|fence| fence.1).max().unwrap();
+    let gap_in_row = |y: i32| (left..=right).any(|x| !fences.contains(&(x, y)));
+    let gap_in_column = |x: i32| (top..=bottom).any(|y| !fences.contains(&(x, y)));
+
+    let open_sides: Vec<Side> = [
+        (Side::Up, gap_in_row(top)),
+        (Side::Down, gap_in_row(bottom)),
+        (Side::Left, gap_in_column(left)),
+        (Side::Right, gap_in_column(right)),
+    ]
+    .into_iter()
+    .filter(|(_, open)| *open)
+    .map(|(side, _)| side)
+    .collect();
+
+    match open_sides[..] {
+        [side] => Ok(side),
+        _ => Err(format!(
+            "pen must open on exactly one side, found {:?}",
+            open_sides
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn every_map_file_round_trips() {
+        let maps = Path::new(env!("CARGO_MANIFEST_DIR")).join("maps");
+        for entry in fs::read_dir(maps).unwrap() {
+            let path = entry.unwrap().path();
+            let text = fs::read_to_string(&path).unwrap();
+            let field = parse_field(&text)
+                .unwrap_or_else(|e| panic!("{} does not parse: {}", path.display(), e));
+            assert_eq!(format_field(&field), text, "{} changed", path.display());
+            assert_eq!(parse_field(&format_field(&field)).unwrap(), field);
+        }
+    }
+
+    #[test]
+    fn sheep_run_into_the_dog_round_trips() {
+        let config = FieldConfig::new(7);
+        let goal = config.pen.goal;
+        for cell in [(0, 0), goal] {
+            let field = Field::with(&config, Sheep::at(cell.0, cell.1), Dog::at(cell.0, cell.1));
+            let parsed = parse_field(&format_field(&field)).unwrap();
+            assert_eq!((parsed.sheep(), parsed.dog()), (field.sheep(), field.dog()));
+            assert!(parsed.sheep_won());
+        }
+    }
+}
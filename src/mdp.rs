@@ -0,0 +1,938 @@
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+// a Markov decision process where the controller picks actions to minimize its expected cost.
+// value iteration can back states up on several threads, hence Sync
+pub trait Mdp: Sync {
+    type State: Clone + Eq + Hash + Send + Sync;
+    type Action;
+    // where the solvers keep the values of the states
+    type Values: ValueTable<Self::State>;
+
+    // every state that gets a value, terminal ones included
+    fn states(&self) -> Vec<Self::State>;
+
+    // a table with room for every state and nothing in it yet
+    fn empty_values(&self) -> Self::Values;
+
+    fn actions(&self, state: &Self::State) -> Vec<Self::Action>;
+
+    // each state the action can lead to, with its probability
+    fn transitions(&self, state: &Self::State, action: &Self::Action) -> Vec<(Self::State, f32)>;
+
+    fn cost(&self, state: &Self::State, action: &Self::Action) -> f32;
+
+    fn is_terminal(&self, state: &Self::State) -> bool;
+
+    // the terminal states the controller is after, rather than the ones where it lost
+    fn is_goal(&self, state: &Self::State) -> bool;
+
+    // what a terminal state is worth; it never changes during the solve
+    fn terminal_value(&self, state: &Self::State) -> f32;
+
+    // the guess value iteration starts from for the other states
+    fn initial_value(&self, state: &Self::State) -> f32;
+}
+
+// one value per state; a game with a dense numbering of its states can use a flat table
+// instead of hashing every lookup
+pub trait ValueTable<S>: Clone + Send + Sync {
+    // the value of a state the table holds; panics for any other
+    fn value(&self, state: &S) -> f32;
+
+    fn set_value(&mut self, state: &S, value: f32);
+}
+
+impl<S: Clone + Eq + Hash + Send + Sync> ValueTable<S> for HashMap<S, f32> {
+    fn value(&self, state: &S) -> f32 {
+        self[state]
+    }
+
+    fn set_value(&mut self, state: &S, value: f32) {
+        self.insert(state.clone(), value);
+    }
+}
+
+// how value iteration walks through the states
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Sweep {
+    // every state is backed up from the values of the previous sweep
+    #[default]
+    Jacobi,
+    // one map updated in place, so later states in a sweep already see the new values of
+    // earlier ones
+    GaussSeidel,
+    // back up the state whose value is furthest off first, and after each backup recheck only
+    // the states that can lead to it
+    Prioritized,
+}
+
+// how the other side of the game picks among the outcomes of an action
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Opponent {
+    // by the transition probabilities, so the solver minimizes the expected cost
+    #[default]
+    Random,
+    // whichever outcome with any chance at all is worst for the controller, so the solver
+    // minimizes the worst case
+    Adversarial,
+}
+
+impl Opponent {
+    // what the outcomes of an action are worth to the controller
+    fn combine(&self, outcomes: impl Iterator<Item = (f32, f32)>) -> f32 {
+        match self {
+            Opponent::Random => outcomes
+                .map(|(value, probability)| probability * value)
+                .sum(),
+            Opponent::Adversarial => outcomes
+                .filter(|(_, probability)| *probability > 0.0)
+                .map(|(value, _)| value)
+                .fold(f32::MIN, f32::max),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SolverOptions {
+    pub sweep: Sweep,
+    // worker threads for Jacobi sweeps; the in place sweeps are sequential by nature and
+    // always run on one
+    pub threads: usize,
+    // how much a step's worth of cost shrinks per step into the future, below 1
+    pub discount: f32,
+    // how far from the true values the answer is allowed to be, in the sup norm
+    pub tolerance: f32,
+    // sweeps to give up after, converged or not. a resumed run gets this many more on top of
+    // the ones it had already done
+    pub max_iterations: usize,
+    // sweeps between checkpoints of a run that can be resumed, 0 for none
+    pub checkpoint_every: usize,
+    // value iteration only; policy iteration only plays a random opponent and refuses any other
+    pub opponent: Opponent,
+}
+
+impl Default for SolverOptions {
+    fn default() -> Self {
+        Self {
+            sweep: Sweep::default(),
+            threads: 1,
+            discount: 0.99,
+            tolerance: 0.01,
+            max_iterations: 10000,
+            checkpoint_every: 10,
+            opponent: Opponent::default(),
+        }
+    }
+}
+
+impl SolverOptions {
+    // the largest Bellman residual that still guarantees the tolerance: once a sweep changes no
+    // value by more than r, the values are within r * discount / (1 - discount) of the fixed point
+    pub fn residual_threshold(&self) -> f32 {
+        self.tolerance * (1.0 - self.discount) / self.discount
+    }
+
+    pub fn error_bound(&self, residual: f32) -> f32 {
+        residual * self.discount / (1.0 - self.discount)
+    }
+}
+
+// how a value iteration run went, or how far it had got when it was checkpointed
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ConvergenceReport {
+    // sweeps, or single state backups for prioritized sweeping, added up over every run
+    pub iterations: usize,
+    // the largest change the last sweep made to any value
+    pub residual: f32,
+    // how far the values can still be from the fixed point, from the residual
+    pub error_bound: f32,
+    // false when the run's max_iterations ran out first
+    pub converged: bool,
+    // wall time, added up over every run a resumed solve took
+    pub elapsed: Duration,
+}
+
+// every action with its cost plus the discounted value of where it leads, expected or worst case
+// depending on the opponent. these are the Q values a backup takes the minimum of
+pub fn q_values<M: Mdp>(
+    mdp: &M,
+    values: &M::Values,
+    state: &M::State,
+    options: &SolverOptions,
+) -> Vec<(M::Action, f32)> {
+    mdp.actions(state)
+        .into_iter()
+        .map(|action| {
+            let outcomes = mdp
+                .transitions(state, &action)
+                .into_iter()
+                .map(|(next_state, probability)| (values.value(&next_state), probability));
+            let value =
+                mdp.cost(state, &action) + options.discount * options.opponent.combine(outcomes);
+            (action, value)
+        })
+        .collect()
+}
+
+// the cheapest action along with its Q value. ties go to the first action, and None means there
+// was nothing to pick from
+fn cheapest_action<M: Mdp>(
+    mdp: &M,
+    values: &M::Values,
+    state: &M::State,
+    options: &SolverOptions,
+) -> Option<(M::Action, f32)> {
+    let mut best = None;
+    let mut minimum = f32::MAX;
+    for (action, value) in q_values(mdp, values, state, options) {
+        if value < minimum {
+            minimum = value;
+            best = Some(action);
+        }
+    }
+    best.map(|action| (action, minimum))
+}
+
+fn backup<M: Mdp>(mdp: &M, values: &M::Values, state: &M::State, options: &SolverOptions) -> f32 {
+    cheapest_action(mdp, values, state, options).map_or(f32::MAX, |(_, value)| value)
+}
+
+// the action a solved value function says to take, from the same expectation a backup minimizes.
+// terminal states have none
+pub fn best_action<M: Mdp>(
+    mdp: &M,
+    values: &M::Values,
+    state: &M::State,
+    options: &SolverOptions,
+) -> Option<M::Action> {
+    if mdp.is_terminal(state) {
+        return None;
+    }
+    cheapest_action(mdp, values, state, options).map(|(action, _)| action)
+}
+
+// one sweep of value iteration, backing up every non terminal state. the states are split into
+// one contiguous run per thread, and since every backup only reads the old values the result is
+// the same however many threads there are
+pub fn bellman_backup<M: Mdp>(
+    mdp: &M,
+    states: &[M::State],
+    old_values: &M::Values,
+    options: &SolverOptions,
+) -> M::Values {
+    let chunk_size = states.len().div_ceil(options.threads.max(1)).max(1);
+    let backed_up: Vec<Vec<Option<f32>>> = thread::scope(|scope| {
+        let workers: Vec<_> = states
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|state| {
+                            if mdp.is_terminal(state) {
+                                None
+                            } else {
+                                Some(backup(mdp, old_values, state, options))
+                            }
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap())
+            .collect()
+    });
+
+    let mut new_values = old_values.clone();
+    for (chunk, values) in states.chunks(chunk_size).zip(backed_up) {
+        for (state, value) in chunk.iter().zip(values) {
+            if let Some(value) = value {
+                new_values.set_value(state, value);
+            }
+        }
+    }
+
+    new_values
+}
+
+// where both solvers start: terminal states at their fixed value, the rest at the guess
+fn starting_value<M: Mdp>(mdp: &M, state: &M::State) -> f32 {
+    if mdp.is_terminal(state) {
+        mdp.terminal_value(state)
+    } else {
+        mdp.initial_value(state)
+    }
+}
+
+// repeat Bellman backups until the residual is small enough for the values to be within the
+// tolerance of the fixed point, or until max_iterations runs out
+pub fn value_iteration<M: Mdp>(mdp: &M, options: &SolverOptions) -> (M::Values, ConvergenceReport) {
+    resume_value_iteration(mdp, options, None, |_, _| {})
+}
+
+// value iteration that can pick up where a checkpoint left off, with the values it had reached and
+// its report so far. every checkpoint_every sweeps the values and the report are handed to
+// checkpoint, which is expected to keep them somewhere safe
+pub fn resume_value_iteration<M: Mdp>(
+    mdp: &M,
+    options: &SolverOptions,
+    resume_from: Option<(M::Values, ConvergenceReport)>,
+    mut checkpoint: impl FnMut(&M::Values, &ConvergenceReport),
+) -> (M::Values, ConvergenceReport) {
+    let start = Instant::now();
+    let states = mdp.states();
+    let mut values = mdp.empty_values();
+    let mut report = ConvergenceReport::default();
+    match resume_from {
+        // terminal values are never backed up, so they come from the game rather than the saved
+        // values in case those were solved with different ones
+        Some((saved, saved_report)) => {
+            for state in states.iter() {
+                let value = if mdp.is_terminal(state) {
+                    mdp.terminal_value(state)
+                } else {
+                    saved.value(state)
+                };
+                values.set_value(state, value);
+            }
+            report = saved_report;
+            report.converged = false;
+        }
+        None => {
+            for state in states.iter() {
+                values.set_value(state, starting_value(mdp, state));
+            }
+        }
+    }
+    let earlier = report.elapsed;
+    let limit = report.iterations.saturating_add(options.max_iterations);
+    let mut save = |values: &M::Values, report: &mut ConvergenceReport| {
+        report.error_bound = options.error_bound(report.residual);
+        report.elapsed = earlier + start.elapsed();
+        checkpoint(values, report);
+    };
+    let due = |iterations: usize| {
+        options.checkpoint_every > 0 && iterations.is_multiple_of(options.checkpoint_every)
+    };
+
+    match options.sweep {
+        Sweep::Jacobi => {
+            while report.iterations < limit {
+                let updated_values = bellman_backup(mdp, &states, &values, options);
+                report.residual = residual(&states, &values, &updated_values);
+                report.iterations += 1;
+                values = updated_values;
+                if report.residual < options.residual_threshold() {
+                    report.converged = true;
+                    break;
+                }
+                if due(report.iterations) {
+                    save(&values, &mut report);
+                }
+            }
+        }
+        Sweep::GaussSeidel => {
+            while report.iterations < limit {
+                let mut largest_change: f32 = 0.0;
+                for state in states.iter() {
+                    if mdp.is_terminal(state) {
+                        continue;
+                    }
+                    let value = backup(mdp, &values, state, options);
+                    largest_change = largest_change.max((value - values.value(state)).abs());
+                    values.set_value(state, value);
+                }
+                report.residual = largest_change;
+                report.iterations += 1;
+                if report.residual < options.residual_threshold() {
+                    report.converged = true;
+                    break;
+                }
+                if due(report.iterations) {
+                    save(&values, &mut report);
+                }
+            }
+        }
+        Sweep::Prioritized => {
+            prioritized_sweeping(mdp, &states, &mut values, options, &mut report, &mut save)
+        }
+    }
+    report.error_bound = options.error_bound(report.residual);
+    report.elapsed = earlier + start.elapsed();
+    (values, report)
+}
+
+// backs states up until every priority is under the residual threshold, or until max_iterations
+// sweeps' worth of backups have been spent. checkpoints come every checkpoint_every sweeps' worth
+fn prioritized_sweeping<M: Mdp>(
+    mdp: &M,
+    states: &[M::State],
+    values: &mut M::Values,
+    options: &SolverOptions,
+    report: &mut ConvergenceReport,
+    save: &mut impl FnMut(&M::Values, &mut ConvergenceReport),
+) {
+    let discount = options.discount;
+    let threshold = options.residual_threshold();
+    let budget = report
+        .iterations
+        .saturating_add(options.max_iterations.saturating_mul(states.len()));
+    let checkpoint_every = options.checkpoint_every.saturating_mul(states.len());
+    let index: HashMap<M::State, usize> = states
+        .iter()
+        .enumerate()
+        .map(|(position, state)| (state.clone(), position))
+        .collect();
+    let terminal: Vec<bool> = states.iter().map(|state| mdp.is_terminal(state)).collect();
+
+    // for every state, the non terminal states that can lead to it, each with the highest
+    // probability any of its actions has of doing so. against an adversary any possible outcome
+    // can carry the whole weight of a backup
+    let mut predecessors = vec![Vec::<(usize, f32)>::new(); states.len()];
+    for (position, state) in states.iter().enumerate() {
+        if terminal[position] {
+            continue;
+        }
+        for action in mdp.actions(state) {
+            for (next_state, probability) in mdp.transitions(state, &action) {
+                let probability = match options.opponent {
+                    Opponent::Random => probability,
+                    Opponent::Adversarial => 1.0,
+                };
+                let next = index[&next_state];
+                match predecessors[next]
+                    .iter_mut()
+                    .find(|(predecessor, _)| *predecessor == position)
+                {
+                    Some((_, highest)) => *highest = highest.max(probability),
+                    None => predecessors[next].push((position, probability)),
+                }
+            }
+        }
+    }
+
+    // a state's priority bounds how far its backup has drifted from its value: its Bellman error
+    // to start with, then whatever the changes to the states it leads to could have added. the
+    // bounds are never negative, so their bit patterns sort the same way the floats do. a state can
+    // sit in the queue more than once; only the entry matching its latest priority counts
+    let mut priority = vec![0.0f32; states.len()];
+    let mut queue = BinaryHeap::new();
+    for (position, state) in states.iter().enumerate() {
+        if terminal[position] {
+            continue;
+        }
+        priority[position] = (backup(mdp, values, state, options) - values.value(state)).abs();
+        if priority[position] > threshold {
+            queue.push((priority[position].to_bits(), position));
+        }
+    }
+
+    while let Some((bits, position)) = queue.pop() {
+        if report.iterations >= budget {
+            break;
+        }
+        if bits != priority[position].to_bits() {
+            continue;
+        }
+        priority[position] = 0.0;
+        let state = &states[position];
+        let value = backup(mdp, values, state, options);
+        let change = (value - values.value(state)).abs();
+        values.set_value(state, value);
+        report.iterations += 1;
+
+        for (predecessor, probability) in predecessors[position].iter() {
+            priority[*predecessor] += discount * probability * change;
+            if priority[*predecessor] > threshold {
+                queue.push((priority[*predecessor].to_bits(), *predecessor));
+            }
+        }
+
+        if checkpoint_every > 0 && report.iterations.is_multiple_of(checkpoint_every) {
+            report.residual = priority
+                .iter()
+                .fold(0.0, |largest, bound| largest.max(*bound));
+            save(values, report);
+        }
+    }
+    // the priorities bound every state's remaining Bellman error
+    report.residual = priority
+        .iter()
+        .fold(0.0, |largest, bound| largest.max(*bound));
+    report.converged = report.residual <= threshold;
+}
+
+// the largest change between two sweeps, the Bellman residual in the sup norm
+fn residual<K, V: ValueTable<K>>(states: &[K], old_map: &V, new_map: &V) -> f32 {
+    let mut largest: f32 = 0.0;
+    for key in states {
+        let difference = old_map.value(key) - new_map.value(key);
+        largest = largest.max(difference.abs());
+    }
+    largest
+}
+
+// how policy iteration works out what the current policy is worth
+#[derive(Clone, Copy, Debug)]
+pub enum Evaluation {
+    // sweep the fixed policy's backup until no value moves by more than tolerance
+    Iterative { tolerance: f32 },
+    // solve the policy's linear equations directly. the matrix is dense, so this is only for
+    // fields with a few thousand states
+    Exact,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PolicyIterationReport {
+    // rounds of evaluation followed by greedy improvement, the last one changing nothing
+    pub improvement_steps: usize,
+    // sweeps spent in iterative evaluation, summed over every round
+    pub evaluation_sweeps: usize,
+    // false when max_iterations rounds went by with the policy still changing
+    pub converged: bool,
+}
+
+// an action's cost and where it leads, as state positions with their probabilities
+type Choice = (f32, Vec<(usize, f32)>);
+
+// the largest state count Evaluation::Exact accepts
+const EXACT_STATE_LIMIT: usize = 5000;
+
+// a switch to another action has to win by more than this, so rounding noise in the evaluation
+// can't make the policy flip back and forth between equally good actions
+const IMPROVEMENT_MARGIN: f32 = 1e-4;
+
+// alternate evaluating the current policy with making it greedy in the values found, until the
+// policy stops changing or max_iterations rounds have gone by. starts from the policy that is
+// greedy in the seed guess. iterative evaluation also gives up after max_iterations sweeps
+pub fn policy_iteration<M: Mdp>(
+    mdp: &M,
+    evaluation: Evaluation,
+    options: &SolverOptions,
+) -> (M::Values, PolicyIterationReport) {
+    assert!(
+        options.opponent == Opponent::Random,
+        "policy iteration only plays a random opponent"
+    );
+    let discount = options.discount;
+    let states = mdp.states();
+    let index: HashMap<M::State, usize> = states
+        .iter()
+        .enumerate()
+        .map(|(position, state)| (state.clone(), position))
+        .collect();
+    let terminal: Vec<bool> = states.iter().map(|state| mdp.is_terminal(state)).collect();
+    let mut values: Vec<f32> = states
+        .iter()
+        .map(|state| starting_value(mdp, state))
+        .collect();
+
+    // the cost and the outcomes of every action of every state, looked up by state position
+    let choices: Vec<Vec<Choice>> = states
+        .iter()
+        .zip(terminal.iter())
+        .map(|(state, is_terminal)| {
+            if *is_terminal {
+                return Vec::new();
+            }
+            mdp.actions(state)
+                .iter()
+                .map(|action| {
+                    let outcomes = mdp
+                        .transitions(state, action)
+                        .into_iter()
+                        .map(|(next_state, probability)| (index[&next_state], probability))
+                        .collect();
+                    (mdp.cost(state, action), outcomes)
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut policy: Vec<usize> = choices
+        .iter()
+        .map(|actions| greedy_action(actions, &values, None, discount))
+        .collect();
+    let mut report = PolicyIterationReport::default();
+
+    while report.improvement_steps < options.max_iterations {
+        match evaluation {
+            Evaluation::Iterative { tolerance } => {
+                report.evaluation_sweeps += evaluate_iteratively(
+                    &choices,
+                    &policy,
+                    &terminal,
+                    &mut values,
+                    tolerance,
+                    options,
+                );
+            }
+            Evaluation::Exact => {
+                evaluate_exactly(&choices, &policy, &terminal, &mut values, discount)
+            }
+        }
+        report.improvement_steps += 1;
+
+        let mut stable = true;
+        for (position, actions) in choices.iter().enumerate() {
+            if terminal[position] {
+                continue;
+            }
+            let best = greedy_action(actions, &values, Some(policy[position]), discount);
+            if best != policy[position] {
+                policy[position] = best;
+                stable = false;
+            }
+        }
+        if stable {
+            report.converged = true;
+            break;
+        }
+    }
+
+    let mut table = mdp.empty_values();
+    for (state, value) in states.iter().zip(values) {
+        table.set_value(state, value);
+    }
+    (table, report)
+}
+
+fn action_value(choice: &Choice, values: &[f32], discount: f32) -> f32 {
+    let (cost, outcomes) = choice;
+    let summation: f32 = outcomes
+        .iter()
+        .map(|(next, probability)| probability * values[*next])
+        .sum();
+    cost + discount * summation
+}
+
+// the cheapest action, sticking with the current one unless another clearly beats it
+fn greedy_action(
+    actions: &[Choice],
+    values: &[f32],
+    current: Option<usize>,
+    discount: f32,
+) -> usize {
+    let mut best = current.unwrap_or(0);
+    let mut best_value = match actions.get(best) {
+        Some(choice) => action_value(choice, values, discount),
+        None => return best,
+    };
+    for (action, choice) in actions.iter().enumerate() {
+        let value = action_value(choice, values, discount);
+        if value < best_value - IMPROVEMENT_MARGIN {
+            best = action;
+            best_value = value;
+        }
+    }
+    best
+}
+
+// returns how many sweeps it took
+fn evaluate_iteratively(
+    choices: &[Vec<Choice>],
+    policy: &[usize],
+    terminal: &[bool],
+    values: &mut Vec<f32>,
+    tolerance: f32,
+    options: &SolverOptions,
+) -> usize {
+    let discount = options.discount;
+    let mut sweeps = 0;
+    loop {
+        let mut new_values = values.clone();
+        let mut largest_change: f32 = 0.0;
+        for (position, actions) in choices.iter().enumerate() {
+            if terminal[position] {
+                continue;
+            }
+            new_values[position] = action_value(&actions[policy[position]], values, discount);
+            largest_change = largest_change.max((new_values[position] - values[position]).abs());
+        }
+        *values = new_values;
+        sweeps += 1;
+        if largest_change < tolerance || sweeps >= options.max_iterations {
+            return sweeps;
+        }
+    }
+}
+
+// solves v = cost + discount * P v over the non terminal states, with the terminal values moved to
+// the right hand side
+fn evaluate_exactly(
+    choices: &[Vec<Choice>],
+    policy: &[usize],
+    terminal: &[bool],
+    values: &mut [f32],
+    discount: f32,
+) {
+    let unknowns: Vec<usize> = (0..choices.len())
+        .filter(|position| !terminal[*position])
+        .collect();
+    assert!(
+        unknowns.len() <= EXACT_STATE_LIMIT,
+        "exact evaluation is limited to {} states, this has {}",
+        EXACT_STATE_LIMIT,
+        unknowns.len()
+    );
+    let mut row_of = vec![usize::MAX; choices.len()];
+    for (row, position) in unknowns.iter().enumerate() {
+        row_of[*position] = row;
+    }
+
+    let mut matrix = vec![vec![0.0f64; unknowns.len()]; unknowns.len()];
+    let mut rhs = vec![0.0f64; unknowns.len()];
+    for (row, position) in unknowns.iter().enumerate() {
+        let (cost, outcomes) = &choices[*position][policy[*position]];
+        matrix[row][row] += 1.0;
+        rhs[row] = *cost as f64;
+        for (next, probability) in outcomes {
+            let weight = discount as f64 * *probability as f64;
+            if terminal[*next] {
+                rhs[row] += weight * values[*next] as f64;
+            } else {
+                matrix[row][row_of[*next]] -= weight;
+            }
+        }
+    }
+
+    for (row, value) in solve_linear(matrix, rhs).into_iter().enumerate() {
+        values[unknowns[row]] = value as f32;
+    }
+}
+
+// gaussian elimination with partial pivoting
+fn solve_linear(mut matrix: Vec<Vec<f64>>, mut rhs: Vec<f64>) -> Vec<f64> {
+    let size = rhs.len();
+    for column in 0..size {
+        let pivot = (column..size)
+            .max_by(|a, b| {
+                matrix[*a][column]
+                    .abs()
+                    .total_cmp(&matrix[*b][column].abs())
+            })
+            .unwrap();
+        matrix.swap(column, pivot);
+        rhs.swap(column, pivot);
+        for row in column + 1..size {
+            let factor = matrix[row][column] / matrix[column][column];
+            if factor == 0.0 {
+                continue;
+            }
+            let (above, below) = matrix.split_at_mut(row);
+            for (entry, pivot_entry) in below[0][column..].iter_mut().zip(&above[column][column..])
+            {
+                *entry -= factor * pivot_entry;
+            }
+            rhs[row] -= factor * rhs[column];
+        }
+    }
+    let mut solution = vec![0.0; size];
+    for row in (0..size).rev() {
+        let mut total = rhs[row];
+        for k in row + 1..size {
+            total -= matrix[row][k] * solution[k];
+        }
+        solution[row] = total / matrix[row][row];
+    }
+    solution
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::field::FieldConfig;
+    use crate::sheep_policy::Flee;
+    use crate::solve_markov::HerdingGame;
+
+    // a three state chain with a closed form. from 0 the controller can step to 1 for 1 or jump
+    // straight to the goal 2 for 3. from 1 it can gamble for 1, reaching the goal or staying put
+    // with even chances, or walk to the goal for 2. with a discount b, gambling and stepping win:
+    // V(1) = 1 / (1 - b / 2) and V(0) = 1 + b V(1)
+    struct Chain;
+
+    impl Mdp for Chain {
+        type State = usize;
+        type Action = &'static str;
+        type Values = HashMap<usize, f32>;
+
+        fn states(&self) -> Vec<usize> {
+            vec![0, 1, 2]
+        }
+
+        fn empty_values(&self) -> HashMap<usize, f32> {
+            HashMap::new()
+        }
+
+        fn actions(&self, state: &usize) -> Vec<&'static str> {
+            match state {
+                0 => vec!["step", "jump"],
+                1 => vec!["gamble", "walk"],
+                _ => Vec::new(),
+            }
+        }
+
+        fn transitions(&self, _state: &usize, action: &&'static str) -> Vec<(usize, f32)> {
+            match *action {
+                "step" => vec![(1, 1.0)],
+                "gamble" => vec![(2, 0.5), (1, 0.5)],
+                _ => vec![(2, 1.0)],
+            }
+        }
+
+        fn cost(&self, _state: &usize, action: &&'static str) -> f32 {
+            match *action {
+                "jump" => 3.0,
+                "walk" => 2.0,
+                _ => 1.0,
+            }
+        }
+
+        fn is_terminal(&self, state: &usize) -> bool {
+            *state == 2
+        }
+
+        fn is_goal(&self, state: &usize) -> bool {
+            *state == 2
+        }
+
+        fn terminal_value(&self, _state: &usize) -> f32 {
+            0.0
+        }
+
+        fn initial_value(&self, _state: &usize) -> f32 {
+            0.0
+        }
+    }
+
+    const DISCOUNT: f32 = 0.9;
+
+    fn chain_values() -> [f32; 3] {
+        let one = 1.0 / (1.0 - DISCOUNT / 2.0);
+        [1.0 + DISCOUNT * one, one, 0.0]
+    }
+
+    fn options(sweep: Sweep, threads: usize) -> SolverOptions {
+        SolverOptions {
+            sweep,
+            threads,
+            discount: DISCOUNT,
+            tolerance: 1e-4,
+            checkpoint_every: 0,
+            ..SolverOptions::default()
+        }
+    }
+
+    fn assert_chain_values(values: &HashMap<usize, f32>, tolerance: f32) {
+        for (state, exact) in chain_values().iter().enumerate() {
+            let difference = (values[&state] - exact).abs();
+            assert!(
+                difference <= tolerance,
+                "V({}) is {} off",
+                state,
+                difference
+            );
+        }
+    }
+
+    #[test]
+    fn value_iteration_reaches_the_chain_values() {
+        for sweep in [Sweep::Jacobi, Sweep::GaussSeidel, Sweep::Prioritized] {
+            let options = options(sweep, 1);
+            let (values, report) = value_iteration(&Chain, &options);
+            assert!(report.converged, "{:?} didn't converge", sweep);
+            assert!(report.error_bound <= options.tolerance);
+            assert_chain_values(&values, options.tolerance);
+        }
+    }
+
+    #[test]
+    fn policy_iteration_reaches_the_chain_values() {
+        for evaluation in [Evaluation::Iterative { tolerance: 1e-6 }, Evaluation::Exact] {
+            let (values, report) = policy_iteration(&Chain, evaluation, &options(Sweep::Jacobi, 1));
+            assert!(report.converged);
+            assert_chain_values(&values, 1e-4);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "only plays a random opponent")]
+    fn policy_iteration_refuses_an_adversary() {
+        let options = SolverOptions {
+            opponent: Opponent::Adversarial,
+            ..options(Sweep::Jacobi, 1)
+        };
+        policy_iteration(&Chain, Evaluation::Exact, &options);
+    }
+
+    #[test]
+    fn threads_give_the_same_chain_values() {
+        let (one, _) = value_iteration(&Chain, &options(Sweep::Jacobi, 1));
+        let (four, _) = value_iteration(&Chain, &options(Sweep::Jacobi, 4));
+        for state in Chain.states() {
+            assert_eq!(one[&state].to_bits(), four[&state].to_bits());
+        }
+    }
+
+    fn herding_options(sweep: Sweep, threads: usize) -> SolverOptions {
+        SolverOptions {
+            discount: 0.95,
+            tolerance: 1e-3,
+            ..options(sweep, threads)
+        }
+    }
+
+    // every sweep stops within the tolerance of the same fixed point, so they end up within twice
+    // the tolerance of each other
+    #[test]
+    fn sweeps_reach_the_same_herding_values() {
+        let policy = Flee::default();
+        let game = HerdingGame::new(&FieldConfig::new(7), &policy);
+        let (jacobi, _) = value_iteration(&game, &herding_options(Sweep::Jacobi, 1));
+        for sweep in [Sweep::GaussSeidel, Sweep::Prioritized] {
+            let options = herding_options(sweep, 1);
+            let (values, report) = value_iteration(&game, &options);
+            assert!(report.converged, "{:?} didn't converge", sweep);
+            for state in game.states() {
+                let difference = (values.value(&state) - jacobi.value(&state)).abs();
+                assert!(
+                    difference <= 2.0 * options.tolerance,
+                    "{:?} is {} off Jacobi at {:?}",
+                    sweep,
+                    difference,
+                    state
+                );
+            }
+        }
+    }
+
+    // every thread count splits the same backups differently, and none of them may change a bit
+    #[test]
+    fn threads_give_the_same_herding_values() {
+        let policy = Flee::default();
+        let game = HerdingGame::new(&FieldConfig::new(7), &policy);
+        let (one, _) = value_iteration(&game, &herding_options(Sweep::Jacobi, 1));
+        for threads in [2, 3, 4, 8] {
+            let (values, _) = value_iteration(&game, &herding_options(Sweep::Jacobi, threads));
+            for state in game.states() {
+                assert_eq!(
+                    values.value(&state).to_bits(),
+                    one.value(&state).to_bits(),
+                    "{} threads changed {:?}",
+                    threads,
+                    state
+                );
+            }
+        }
+    }
+}
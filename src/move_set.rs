@@ -0,0 +1,177 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Neighborhood {
+    // the four orthogonal neighbours
+    VonNeumann,
+    // all eight neighbours, diagonals included
+    Moore,
+}
+
+impl Neighborhood {
+    fn steps(&self) -> &'static [(i32, i32)] {
+        match self {
+            Neighborhood::VonNeumann => &[(0, -1), (-1, 0), (1, 0), (0, 1)],
+            Neighborhood::Moore => &[
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ],
+        }
+    }
+}
+
+// how an agent gets around: one move is up to speed steps into its neighborhood, and waiting
+// lets it stay on its cell instead
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct MoveSet {
+    pub neighborhood: Neighborhood,
+    pub speed: i32,
+    pub can_wait: bool,
+}
+
+impl MoveSet {
+    // how the sheep has always moved
+    pub fn von_neumann() -> Self {
+        Self {
+            neighborhood: Neighborhood::VonNeumann,
+            speed: 1,
+            can_wait: false,
+        }
+    }
+
+    // how the dog has always moved
+    pub fn moore() -> Self {
+        Self {
+            neighborhood: Neighborhood::Moore,
+            speed: 1,
+            can_wait: false,
+        }
+    }
+
+    pub fn with_speed(mut self, speed: i32) -> Self {
+        assert!(speed >= 1, "speed must be at least 1, got {}", speed);
+        self.speed = speed;
+        self
+    }
+
+    pub fn with_wait(mut self) -> Self {
+        self.can_wait = true;
+        self
+    }
+
+    // every cell one move away from `from`, top to bottom and left to right. each step but the
+    // last has to go through a cell that is_clear, and the move has to end on one that can_land;
+    // the agent's own cell is only included when it can wait
+    pub fn targets(
+        &self,
+        from: (i32, i32),
+        is_clear: impl Fn(i32, i32) -> bool,
+        can_land: impl Fn(i32, i32) -> bool,
+    ) -> Vec<(i32, i32)> {
+        let mut seen = vec![from];
+        let mut frontier = vec![from];
+        let mut targets = Vec::new();
+        for _ in 0..self.speed {
+            let mut next_frontier = Vec::new();
+            for (x, y) in frontier {
+                for (dx, dy) in self.neighborhood.steps() {
+                    let cell = (x + dx, y + dy);
+                    if seen.contains(&cell) {
+                        continue;
+                    }
+                    seen.push(cell);
+                    if can_land(cell.0, cell.1) {
+                        targets.push(cell);
+                    }
+                    if is_clear(cell.0, cell.1) {
+                        next_frontier.push(cell);
+                    }
+                }
+            }
+            frontier = next_frontier;
+        }
+        if self.can_wait {
+            targets.push(from);
+        }
+        targets.sort_by_key(|(x, y)| (*y, *x));
+        targets
+    }
+
+    // the fewest moves it takes to cover an offset on an open field
+    pub fn distance(&self, dx: i32, dy: i32) -> i32 {
+        let steps = match self.neighborhood {
+            Neighborhood::VonNeumann => dx.abs() + dy.abs(),
+            Neighborhood::Moore => dx.abs().max(dy.abs()),
+        };
+        (steps + self.speed - 1) / self.speed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(_: i32, _: i32) -> bool {
+        true
+    }
+
+    #[test]
+    fn neighborhoods_reach_their_neighbours() {
+        assert_eq!(MoveSet::von_neumann().targets((2, 2), open, open).len(), 4);
+        assert_eq!(MoveSet::moore().targets((2, 2), open, open).len(), 8);
+    }
+
+    #[test]
+    fn waiting_adds_the_current_cell() {
+        let targets = MoveSet::von_neumann()
+            .with_wait()
+            .targets((2, 2), open, open);
+        assert_eq!(targets, vec![(2, 1), (1, 2), (2, 2), (3, 2), (2, 3)]);
+        assert_eq!(
+            MoveSet::moore()
+                .with_wait()
+                .targets((2, 2), open, open)
+                .len(),
+            9
+        );
+        assert!(!MoveSet::moore()
+            .targets((2, 2), open, open)
+            .contains(&(2, 2)));
+    }
+
+    // with the cell above blocked, the cell two above can't be reached but the ones on either
+    // side of it still can, around the block
+    #[test]
+    fn fast_moves_go_around_a_blocked_cell() {
+        let free = |x: i32, y: i32| (x, y) != (2, 1);
+        let targets = MoveSet::von_neumann()
+            .with_speed(2)
+            .targets((2, 2), free, free);
+        assert_eq!(
+            MoveSet::von_neumann()
+                .with_speed(2)
+                .targets((2, 2), open, open)
+                .len(),
+            12
+        );
+        assert_eq!(targets.len(), 10);
+        assert!(!targets.contains(&(2, 1)));
+        assert!(!targets.contains(&(2, 0)));
+        assert!(targets.contains(&(1, 1)) && targets.contains(&(3, 1)));
+    }
+
+    #[test]
+    fn distance_counts_moves() {
+        assert_eq!(MoveSet::von_neumann().distance(3, -1), 4);
+        assert_eq!(MoveSet::moore().distance(3, -1), 3);
+        assert_eq!(MoveSet::von_neumann().with_speed(2).distance(3, -1), 2);
+        assert_eq!(MoveSet::moore().with_speed(2).distance(-5, 0), 3);
+        assert_eq!(MoveSet::moore().distance(0, 0), 0);
+    }
+}
@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+
+use crate::field::Dog;
+use crate::field::Sheep;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Side {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Side {
+    // one step out of the pen through this side
+    pub fn offset(&self) -> (i32, i32) {
+        match self {
+            Side::Up => (0, -1),
+            Side::Down => (0, 1),
+            Side::Left => (-1, 0),
+            Side::Right => (1, 0),
+        }
+    }
+
+    // turn an offset drawn for a pen that opens upward so it faces this side instead
+    fn orient(&self, offset: (i32, i32)) -> (i32, i32) {
+        let (x, y) = offset;
+        match self {
+            Side::Up => (x, y),
+            Side::Down => (-x, -y),
+            Side::Left => (y, -x),
+            Side::Right => (-y, x),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum PenShape {
+    // a rectangle fenced on three sides, drawn opening upward from the back middle cell
+    Cup { width: i32, depth: i32 },
+    // fence cells as offsets from the pen position, used exactly as given
+    Custom(Vec<(i32, i32)>),
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct Pen {
+    pub position: (i32, i32),
+    pub shape: PenShape,
+    pub opening: Side,
+    pub goal: (i32, i32),
+}
+
+impl Pen {
+    pub fn new(position: (i32, i32), shape: PenShape, opening: Side) -> Self {
+        Self {
+            position,
+            shape,
+            opening,
+            goal: position,
+        }
+    }
+
+    // the 3x3 pen opening upward that the game was designed around
+    pub fn centered(size: i32) -> Self {
+        let middle = size / 2;
+        Pen::new(
+            (middle, middle),
+            PenShape::Cup { width: 1, depth: 1 },
+            Side::Up,
+        )
+    }
+
+    pub fn fences(&self) -> Vec<(i32, i32)> {
+        let offsets = match &self.shape {
+            PenShape::Cup { width, depth } => {
+                let left = -(width - 1) / 2 - 1;
+                let right = width / 2 + 1;
+                let mut offsets = Vec::new();
+                // side walls, including the corners on either side of the mouth
+                for y in -depth..2 {
+                    offsets.push(self.opening.orient((left, y)));
+                    offsets.push(self.opening.orient((right, y)));
+                }
+                // back wall
+                for x in left + 1..right {
+                    offsets.push(self.opening.orient((x, 1)));
+                }
+                offsets
+            }
+            PenShape::Custom(offsets) => offsets.clone(),
+        };
+        offsets
+            .into_iter()
+            .map(|(x, y)| (self.position.0 + x, self.position.1 + y))
+            .collect()
+    }
+
+    // cells strictly inside the bounding box of the fences that are not fences themselves
+    pub fn interior(&self) -> Vec<(i32, i32)> {
+        let fences = self.fences();
+        let left = fences.iter().map(|fence| fence.0).min().unwrap_or(0);
+        let right = fences.iter().map(|fence| fence.0).max().unwrap_or(0);
+        let top = fences.iter().map(|fence| fence.1).min().unwrap_or(0);
+        let bottom = fences.iter().map(|fence| fence.1).max().unwrap_or(0);
+        let mut cells = Vec::new();
+        for y in top + 1..bottom {
+            for x in left + 1..right {
+                if !fences.contains(&(x, y)) {
+                    cells.push((x, y));
+                }
+            }
+        }
+        cells
+    }
+
+    // the cell right in front of the goal, on the open side
+    pub fn entrance(&self) -> (i32, i32) {
+        let offset = self.opening.offset();
+        (self.goal.0 + offset.0, self.goal.1 + offset.1)
+    }
+
+    // a sheep is penned once it is inside, or is stuck in front of the goal with the dog inside.
+    // encloses says whether a cell is in the interior, so a terrain can answer from its own table
+    pub fn holds(&self, sheep: Sheep, dog: Dog, encloses: impl Fn(i32, i32) -> bool) -> bool {
+        encloses(sheep.x, sheep.y)
+            || ((dog.x, dog.y) == self.goal && (sheep.x, sheep.y) == self.entrance())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 1x1 cup is the ring of cells around its middle with a gap on the open side, and the
+    // sheep is penned in the middle or in the gap with the dog behind it
+    #[test]
+    fn a_small_cup_opens_on_its_side() {
+        for side in [Side::Up, Side::Down, Side::Left, Side::Right] {
+            let pen = Pen::new((4, 4), PenShape::Cup { width: 1, depth: 1 }, side);
+            let mouth = (4 + side.offset().0, 4 + side.offset().1);
+            let mut fences = pen.fences();
+            fences.sort();
+            let mut ring = Vec::new();
+            for x in 3..6 {
+                for y in 3..6 {
+                    if (x, y) != (4, 4) && (x, y) != mouth {
+                        ring.push((x, y));
+                    }
+                }
+            }
+            assert_eq!(fences, ring, "{:?}", side);
+            assert_eq!(pen.interior(), vec![(4, 4)], "{:?}", side);
+            assert_eq!(pen.entrance(), mouth, "{:?}", side);
+
+            let encloses = |x, y| (x, y) == (4, 4);
+            let outside = (4 + 2 * side.offset().0, 4 + 2 * side.offset().1);
+            let holds = |sheep: (i32, i32), dog: (i32, i32)| {
+                pen.holds(Sheep::at(sheep.0, sheep.1), Dog::at(dog.0, dog.1), encloses)
+            };
+            assert!(holds((4, 4), (0, 0)), "{:?}", side);
+            assert!(holds(mouth, (4, 4)), "{:?}", side);
+            assert!(!holds(mouth, (0, 0)), "{:?}", side);
+            assert!(!holds(outside, (4, 4)), "{:?}", side);
+        }
+    }
+}
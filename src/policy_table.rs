@@ -0,0 +1,42 @@
+use crate::field::Dog;
+use crate::field::Sheep;
+use crate::terrain::state_at;
+use crate::terrain::state_index;
+
+// the cell the dog should move to in every (sheep, dog) state, laid out like UtilityTable.
+// terminal and invalid states have no move
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PolicyTable {
+    size: i32,
+    moves: Vec<Option<Dog>>,
+}
+
+impl PolicyTable {
+    pub fn new(size: i32) -> Self {
+        let area = (size * size) as usize;
+        Self {
+            size,
+            moves: vec![None; area * area],
+        }
+    }
+
+    pub fn get(&self, state: (Sheep, Dog)) -> Option<Dog> {
+        state_index(self.size, state).and_then(|index| self.moves[index])
+    }
+
+    pub fn set(&mut self, state: (Sheep, Dog), dog_move: Dog) {
+        let index = state_index(self.size, state)
+            .unwrap_or_else(|| panic!("{:?} is off a {}x{} table", state, self.size, self.size));
+        self.moves[index] = Some(dog_move);
+    }
+
+    // the states with a move, sheep row by row and then dog row by row
+    pub fn iter(&self) -> impl Iterator<Item = ((Sheep, Dog), Dog)> + '_ {
+        self.moves
+            .iter()
+            .enumerate()
+            .filter_map(|(index, dog_move)| {
+                dog_move.map(|dog_move| (state_at(self.size, index), dog_move))
+            })
+    }
+}
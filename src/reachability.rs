@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+use crate::mdp::Mdp;
+use crate::mdp::ValueTable;
+
+// what a search from a set of starting states found out about the state space. every list is in
+// the order Mdp::states gives the states in
+#[derive(Clone, Debug)]
+pub struct StateAnalysis<S> {
+    // every state a game from one of the starts can get to, whatever either side does
+    pub reachable: Vec<S>,
+    // the reachable states where the game is over
+    pub terminal: Vec<S>,
+    // reachable states that are not over but that no action can leave
+    pub absorbing: Vec<S>,
+    // reachable states that are not over and from which no policy ever reaches a goal
+    pub traps: Vec<S>,
+    // how many states Mdp::states lists that no game from the starts can get to
+    pub unreachable: usize,
+}
+
+// search forward from the starts through every action and every outcome with a chance of
+// happening, then backward from the goals to find the states that can still get to one
+pub fn analyze<M: Mdp>(mdp: &M, starts: &[M::State]) -> StateAnalysis<M::State> {
+    let states = mdp.states();
+    let index: HashMap<M::State, usize> = states
+        .iter()
+        .enumerate()
+        .map(|(position, state)| (state.clone(), position))
+        .collect();
+
+    // forward, keeping for every state the states it can lead to
+    let mut reached = vec![false; states.len()];
+    let mut successors = vec![Vec::<usize>::new(); states.len()];
+    let mut queue = VecDeque::new();
+    for start in starts {
+        let position = *index
+            .get(start)
+            .expect("every start has to be a state of the game");
+        if !reached[position] {
+            reached[position] = true;
+            queue.push_back(position);
+        }
+    }
+    while let Some(position) = queue.pop_front() {
+        let state = &states[position];
+        if mdp.is_terminal(state) {
+            continue;
+        }
+        for action in mdp.actions(state) {
+            for (next_state, probability) in mdp.transitions(state, &action) {
+                if probability <= 0.0 {
+                    continue;
+                }
+                let next = index[&next_state];
+                if !successors[position].contains(&next) {
+                    successors[position].push(next);
+                }
+                if !reached[next] {
+                    reached[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    // backward from the reachable goals along the same edges
+    let mut predecessors = vec![Vec::<usize>::new(); states.len()];
+    for (position, nexts) in successors.iter().enumerate() {
+        for next in nexts {
+            predecessors[*next].push(position);
+        }
+    }
+    let mut can_finish = vec![false; states.len()];
+    for (position, state) in states.iter().enumerate() {
+        if reached[position] && mdp.is_goal(state) {
+            can_finish[position] = true;
+            queue.push_back(position);
+        }
+    }
+    while let Some(position) = queue.pop_front() {
+        for predecessor in predecessors[position].iter() {
+            if !can_finish[*predecessor] {
+                can_finish[*predecessor] = true;
+                queue.push_back(*predecessor);
+            }
+        }
+    }
+
+    let mut analysis = StateAnalysis {
+        reachable: Vec::new(),
+        terminal: Vec::new(),
+        absorbing: Vec::new(),
+        traps: Vec::new(),
+        unreachable: 0,
+    };
+    for (position, state) in states.iter().enumerate() {
+        if !reached[position] {
+            analysis.unreachable += 1;
+            continue;
+        }
+        analysis.reachable.push(state.clone());
+        if mdp.is_terminal(state) {
+            analysis.terminal.push(state.clone());
+            continue;
+        }
+        if successors[position].iter().all(|next| *next == position) {
+            analysis.absorbing.push(state.clone());
+        }
+        if !can_finish[position] {
+            analysis.traps.push(state.clone());
+        }
+    }
+    analysis
+}
+
+// the fewest moves in which the controller can force the game into a goal whatever outcome each
+// move has, for every state: 0 on a goal and infinity where no strategy forces one. the states
+// with a finite time are the attractor of the goals, built up one move at a time backwards
+pub fn forced_times<M: Mdp>(mdp: &M) -> M::Values {
+    let states = mdp.states();
+    let index: HashMap<M::State, usize> = states
+        .iter()
+        .enumerate()
+        .map(|(position, state)| (state.clone(), position))
+        .collect();
+
+    // for every action of every non terminal state, how many of its possible outcomes are not
+    // forced yet, and for every state the actions that can lead to it
+    let mut unforced = vec![Vec::<usize>::new(); states.len()];
+    let mut leads_to = vec![Vec::<(usize, usize)>::new(); states.len()];
+    for (position, state) in states.iter().enumerate() {
+        if mdp.is_terminal(state) {
+            continue;
+        }
+        for (action_number, action) in mdp.actions(state).iter().enumerate() {
+            let mut outcomes: Vec<usize> = mdp
+                .transitions(state, action)
+                .into_iter()
+                .filter(|(_, probability)| *probability > 0.0)
+                .map(|(next_state, _)| index[&next_state])
+                .collect();
+            outcomes.sort();
+            outcomes.dedup();
+            unforced[position].push(outcomes.len());
+            for next in outcomes {
+                leads_to[next].push((position, action_number));
+            }
+        }
+    }
+
+    let mut times = vec![f32::INFINITY; states.len()];
+    let mut frontier: Vec<usize> = (0..states.len())
+        .filter(|position| mdp.is_goal(&states[*position]))
+        .collect();
+    for position in frontier.iter() {
+        times[*position] = 0.0;
+    }
+    let mut moves = 0.0;
+    while !frontier.is_empty() {
+        moves += 1.0;
+        let mut next_frontier = Vec::new();
+        for next in frontier {
+            for (position, action_number) in leads_to[next].iter() {
+                unforced[*position][*action_number] -= 1;
+                if unforced[*position][*action_number] == 0 && times[*position].is_infinite() {
+                    times[*position] = moves;
+                    next_frontier.push(*position);
+                }
+            }
+        }
+        frontier = next_frontier;
+    }
+
+    let mut values = mdp.empty_values();
+    for (state, time) in states.iter().zip(times) {
+        values.set_value(state, time);
+    }
+    values
+}
+
+// the same game cut down to a set of states, like the reachable ones. the set has to be closed:
+// every outcome of every action of a non terminal state in it must be in it too
+pub struct Restricted<'a, M: Mdp> {
+    mdp: &'a M,
+    states: Vec<M::State>,
+}
+
+impl<'a, M: Mdp> Restricted<'a, M> {
+    pub fn new(mdp: &'a M, states: Vec<M::State>) -> Self {
+        Self { mdp, states }
+    }
+}
+
+impl<M: Mdp> Mdp for Restricted<'_, M> {
+    type State = M::State;
+    type Action = M::Action;
+    type Values = M::Values;
+
+    fn states(&self) -> Vec<M::State> {
+        self.states.clone()
+    }
+
+    fn empty_values(&self) -> M::Values {
+        self.mdp.empty_values()
+    }
+
+    fn actions(&self, state: &M::State) -> Vec<M::Action> {
+        self.mdp.actions(state)
+    }
+
+    fn transitions(&self, state: &M::State, action: &M::Action) -> Vec<(M::State, f32)> {
+        self.mdp.transitions(state, action)
+    }
+
+    fn cost(&self, state: &M::State, action: &M::Action) -> f32 {
+        self.mdp.cost(state, action)
+    }
+
+    fn is_terminal(&self, state: &M::State) -> bool {
+        self.mdp.is_terminal(state)
+    }
+
+    fn is_goal(&self, state: &M::State) -> bool {
+        self.mdp.is_goal(state)
+    }
+
+    fn terminal_value(&self, state: &M::State) -> f32 {
+        self.mdp.terminal_value(state)
+    }
+
+    fn initial_value(&self, state: &M::State) -> f32 {
+        self.mdp.initial_value(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0 is the goal and 5 is lost. 1 steps to the goal. 2 can walk to 1, or toss a coin between
+    // the goal and 3, which loops forever. 4 only has the coin, so the goal is reachable from it
+    // but can't be forced. 6 has an outcome without a chance, which doesn't count
+    struct Hand;
+
+    impl Mdp for Hand {
+        type State = usize;
+        type Action = &'static str;
+        type Values = HashMap<usize, f32>;
+
+        fn states(&self) -> Vec<usize> {
+            (0..7).collect()
+        }
+
+        fn empty_values(&self) -> HashMap<usize, f32> {
+            HashMap::new()
+        }
+
+        fn actions(&self, state: &usize) -> Vec<&'static str> {
+            match state {
+                1 | 6 => vec!["step"],
+                2 => vec!["coin", "walk"],
+                3 => vec!["stay"],
+                4 => vec!["coin"],
+                _ => Vec::new(),
+            }
+        }
+
+        fn transitions(&self, state: &usize, action: &&'static str) -> Vec<(usize, f32)> {
+            match (*state, *action) {
+                (6, _) => vec![(0, 1.0), (3, 0.0)],
+                (_, "step") => vec![(0, 1.0)],
+                (_, "walk") => vec![(1, 1.0)],
+                (_, "coin") => vec![(0, 0.5), (3, 0.5)],
+                _ => vec![(3, 1.0)],
+            }
+        }
+
+        fn cost(&self, _state: &usize, _action: &&'static str) -> f32 {
+            1.0
+        }
+
+        fn is_terminal(&self, state: &usize) -> bool {
+            *state == 0 || *state == 5
+        }
+
+        fn is_goal(&self, state: &usize) -> bool {
+            *state == 0
+        }
+
+        fn terminal_value(&self, _state: &usize) -> f32 {
+            0.0
+        }
+
+        fn initial_value(&self, _state: &usize) -> f32 {
+            0.0
+        }
+    }
+
+    #[test]
+    fn forced_times_only_count_sure_ways_to_the_goal() {
+        let times = forced_times(&Hand);
+        let never = f32::INFINITY;
+        let expected = [0.0, 1.0, 2.0, never, never, never, 1.0];
+        for (state, time) in expected.iter().enumerate() {
+            assert_eq!(times[&state], *time, "state {}", state);
+        }
+    }
+
+    #[test]
+    fn the_analysis_finds_the_trap_and_what_can_be_reached() {
+        let analysis = analyze(&Hand, &[4]);
+        assert_eq!(analysis.reachable, vec![0, 3, 4]);
+        assert_eq!(analysis.terminal, vec![0]);
+        assert_eq!(analysis.absorbing, vec![3]);
+        assert_eq!(analysis.traps, vec![3]);
+        assert_eq!(analysis.unreachable, 4);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::field::Cell;
+use crate::field::Field;
+
+// how a sheep reacts to the field: every cell it may step to next, with the chance of each.
+// policies are shared between solver threads, hence Sync
+pub trait SheepPolicy: Sync {
+    fn next_cells(&self, field: &Field, index: usize) -> Vec<(Cell, f32)>;
+
+    // tells the policy apart from any that reacts differently, so a solve saved for one isn't read
+    // back for another
+    fn name(&self) -> String;
+
+    // whether a field with one dog reflected left to right gets the reflected reaction, which lets
+    // the solver keep only half the states of a symmetric terrain. no unless a policy says so
+    fn is_mirror_symmetric(&self) -> bool {
+        false
+    }
+}
+
+fn uniform(cells: Vec<Cell>) -> Vec<(Cell, f32)> {
+    let probability = 1.0 / cells.len() as f32;
+    cells.into_iter().map(|cell| (cell, probability)).collect()
+}
+
+// the rule the game was built around: with a dog in view, the sheep only considers the moves
+// that shrink its distance to that dog along the axis it moves on, otherwise it wanders
+#[derive(Clone, Copy, Debug)]
+pub struct Flee {
+    pub view_radius: i32,
+}
+
+impl Default for Flee {
+    fn default() -> Self {
+        Self { view_radius: 2 }
+    }
+}
+
+impl SheepPolicy for Flee {
+    fn next_cells(&self, field: &Field, index: usize) -> Vec<(Cell, f32)> {
+        let mut possible_moves = Vec::<Cell>::new();
+        let sheep = field.flock[index];
+
+        if let Some(dog) = field.dog_in_view(index, self.view_radius) {
+            let position = (dog.x, dog.y);
+            let x = sheep.x;
+            let y = sheep.y;
+
+            for sheep_move in field.get_sheep_moves(index) {
+                if (sheep_move.x - position.0).abs() < (x - position.0).abs() {
+                    possible_moves.push(sheep_move);
+                }
+                if (sheep_move.y - position.1).abs() < (y - position.1).abs() {
+                    possible_moves.push(sheep_move);
+                }
+            }
+
+            if possible_moves.is_empty() {
+                possible_moves = field.get_sheep_moves(index);
+            }
+        } else {
+            possible_moves = field.get_sheep_moves(index);
+        }
+        uniform(possible_moves)
+    }
+
+    fn name(&self) -> String {
+        format!("flee, seeing {} cells", self.view_radius)
+    }
+
+    fn is_mirror_symmetric(&self) -> bool {
+        true
+    }
+}
+
+// the same reaction judged by straight line distance: the sheep only takes the moves that bring
+// it closest to the dog, rather than any move that closes the gap along an axis
+#[derive(Clone, Copy, Debug)]
+pub struct EuclideanFlee {
+    pub view_radius: i32,
+}
+
+impl Default for EuclideanFlee {
+    fn default() -> Self {
+        Self { view_radius: 2 }
+    }
+}
+
+impl SheepPolicy for EuclideanFlee {
+    fn next_cells(&self, field: &Field, index: usize) -> Vec<(Cell, f32)> {
+        let sheep = field.flock[index];
+        let mut possible_moves = field.get_sheep_moves(index);
+
+        if let Some(dog) = field.dog_in_view(index, self.view_radius) {
+            let distance = |x: i32, y: i32| (x - dog.x) * (x - dog.x) + (y - dog.y) * (y - dog.y);
+            let closest = possible_moves
+                .iter()
+                .map(|sheep_move| distance(sheep_move.x, sheep_move.y))
+                .min();
+            if let Some(closest) = closest.filter(|closest| *closest < distance(sheep.x, sheep.y)) {
+                possible_moves.retain(|sheep_move| distance(sheep_move.x, sheep_move.y) == closest);
+            }
+        }
+        uniform(possible_moves)
+    }
+
+    fn name(&self) -> String {
+        format!("euclidean flee, seeing {} cells", self.view_radius)
+    }
+
+    fn is_mirror_symmetric(&self) -> bool {
+        true
+    }
+}
+
+// wanders uniformly no matter where the dogs are
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RandomWalk;
+
+impl SheepPolicy for RandomWalk {
+    fn next_cells(&self, field: &Field, index: usize) -> Vec<(Cell, f32)> {
+        uniform(field.get_sheep_moves(index))
+    }
+
+    fn name(&self) -> String {
+        "random walk".to_string()
+    }
+
+    fn is_mirror_symmetric(&self) -> bool {
+        true
+    }
+}
+
+// stays put with probability stay_probability, otherwise behaves like the wrapped policy
+pub struct Lazy {
+    pub inner: Box<dyn SheepPolicy>,
+    pub stay_probability: f32,
+}
+
+impl Lazy {
+    pub fn new(inner: Box<dyn SheepPolicy>, stay_probability: f32) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&stay_probability),
+            "stay probability must be between 0 and 1, got {}",
+            stay_probability
+        );
+        Self {
+            inner,
+            stay_probability,
+        }
+    }
+}
+
+impl SheepPolicy for Lazy {
+    fn next_cells(&self, field: &Field, index: usize) -> Vec<(Cell, f32)> {
+        let mut cells = vec![(field.flock[index].as_cell(), self.stay_probability)];
+        for (cell, probability) in self.inner.next_cells(field, index) {
+            cells.push((cell, (1.0 - self.stay_probability) * probability));
+        }
+        cells
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "{}, staying put {} of the time",
+            self.inner.name(),
+            self.stay_probability
+        )
+    }
+
+    fn is_mirror_symmetric(&self) -> bool {
+        self.inner.is_mirror_symmetric()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::field::Dog;
+    use crate::field::FieldConfig;
+    use crate::field::Sheep;
+
+    use std::collections::HashMap;
+
+    fn policies() -> Vec<(&'static str, Box<dyn SheepPolicy>)> {
+        vec![
+            ("flee", Box::new(Flee::default())),
+            ("euclidean flee", Box::new(EuclideanFlee::default())),
+            ("random walk", Box::new(RandomWalk)),
+            ("lazy", Box::new(Lazy::new(Box::new(Flee::default()), 0.25))),
+        ]
+    }
+
+    // the chance of each cell, with cells listed more than once added up
+    fn distribution(policy: &dyn SheepPolicy, field: &Field) -> HashMap<(i32, i32), f32> {
+        let mut cells = HashMap::new();
+        for (cell, probability) in policy.next_cells(field, 0) {
+            *cells.entry((cell.x, cell.y)).or_insert(0.0) += probability;
+        }
+        cells
+    }
+
+    // every valid single dog field where the sheep has somewhere to go
+    fn fields(config: &FieldConfig) -> Vec<Field> {
+        let mut fields = Vec::new();
+        for sheep in 0..config.size * config.size {
+            for dog in 0..config.size * config.size {
+                let sheep = Sheep::at(sheep % config.size, sheep / config.size);
+                let dog = Dog::at(dog % config.size, dog / config.size);
+                let field = Field::with(config, sheep, dog);
+                if field.is_valid() && !field.get_sheep_moves(0).is_empty() {
+                    fields.push(field);
+                }
+            }
+        }
+        fields
+    }
+
+    #[test]
+    fn every_policy_gives_a_distribution() {
+        let config = FieldConfig::new(7);
+        for (name, policy) in policies() {
+            for field in fields(&config) {
+                let cells = distribution(policy.as_ref(), &field);
+                let total: f32 = cells.values().sum();
+                assert!(
+                    (total - 1.0).abs() < 1e-6,
+                    "{} sums to {} for {:?}",
+                    name,
+                    total,
+                    (field.sheep(), field.dog())
+                );
+                assert!(cells.values().all(|probability| *probability > 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn symmetric_policies_react_to_the_mirror_image_in_mirror_image() {
+        let config = FieldConfig::new(7);
+        let mirror = |(x, y): (i32, i32)| (config.size - 1 - x, y);
+        for (name, policy) in policies() {
+            assert!(policy.is_mirror_symmetric(), "{}", name);
+            for field in fields(&config) {
+                let (sheep, dog) = (field.sheep(), field.dog());
+                let (sheep_x, sheep_y) = mirror((sheep.x, sheep.y));
+                let (dog_x, dog_y) = mirror((dog.x, dog.y));
+                let reflected =
+                    Field::with(&config, Sheep::at(sheep_x, sheep_y), Dog::at(dog_x, dog_y));
+                let cells = distribution(policy.as_ref(), &field);
+                let reflected_cells = distribution(policy.as_ref(), &reflected);
+                assert_eq!(cells.len(), reflected_cells.len(), "{}", name);
+                for (cell, probability) in cells {
+                    let other = reflected_cells[&mirror(cell)];
+                    assert!(
+                        (probability - other).abs() < 1e-6,
+                        "{} at {:?}: {} against {}",
+                        name,
+                        (sheep, dog),
+                        probability,
+                        other
+                    );
+                }
+            }
+        }
+    }
+
+    // a policy that says nothing about symmetry, and a lazy one wrapped around it
+    struct Unmarked;
+
+    impl SheepPolicy for Unmarked {
+        fn next_cells(&self, field: &Field, index: usize) -> Vec<(Cell, f32)> {
+            RandomWalk.next_cells(field, index)
+        }
+
+        fn name(&self) -> String {
+            "unmarked".to_string()
+        }
+    }
+
+    #[test]
+    fn policies_are_not_symmetric_unless_they_say_so() {
+        assert!(!Unmarked.is_mirror_symmetric());
+        assert!(!Lazy::new(Box::new(Unmarked), 0.5).is_mirror_symmetric());
+    }
+}
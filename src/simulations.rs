@@ -1,116 +1,163 @@
-
-
 use rand::Rng;
-use crate::field::Entity;
-use crate::field::Cell;
+use crate::deadline::DeadlinePolicy;
 use crate::field::Field;
+use crate::field::FieldConfig;
 use crate::field::Dog;
 use crate::field::Sheep;
-use crate::make_distance_map_sheep;
 use crate::math::dot_product;
-use crate::math::model_1;
 use crate::math::model_2;
+use crate::policy_table::PolicyTable;
+use crate::sheep_policy::SheepPolicy;
+use crate::solve_markov::pack_key;
+use crate::solve_markov::PackState;
+use crate::utility_table::UtilityTable;
 use std::collections::HashMap;
 
-pub(crate) fn find_best_starting_location(map: &HashMap<(Sheep, Dog), f32>) -> Field {
-    let test_field = Field::new();
+pub(crate) fn find_best_starting_location(
+    map: &UtilityTable,
+    config: &FieldConfig,
+) -> Field {
+    let test_field = Field::new(config);
     let mut lowest_score = f32::MAX;
     let mut best_field = test_field.clone();
 
-    for dog_cell in test_field
-        .grid
-        .clone()
-        .into_iter()
-        .flatten()
-        .collect::<Vec<Cell>>()
-    {
-        let mut new_dog_cell = dog_cell.clone();
-        new_dog_cell.entity = Entity::Dog;
-        let intermediate_state = test_field.move_dog_to(new_dog_cell);
-        let mut score = 0.0;
-        for sheep_cell in intermediate_state
-            .grid
-            .clone()
-            .into_iter()
-            .flatten()
-            .collect::<Vec<Cell>>()
-        {
-            let mut new_sheep_cell = sheep_cell.clone();
-            new_sheep_cell.entity = Entity::Sheep;
-            let final_state = intermediate_state.move_sheep_to(new_sheep_cell);
-            if !final_state.is_valid() {
-                continue;
+    for dog_y in 0..config.size {
+        for dog_x in 0..config.size {
+            let intermediate_state = test_field.move_dog_to(0, Dog::at(dog_x, dog_y).as_cell());
+            let mut score = 0.0;
+            for sheep_y in 0..config.size {
+                for sheep_x in 0..config.size {
+                    let final_state =
+                        intermediate_state.move_sheep_to(0, Sheep::at(sheep_x, sheep_y).as_cell());
+                    if !final_state.is_valid() {
+                        continue;
+                    }
+                    let subscore = map.get((final_state.sheep(), final_state.dog())).unwrap();
+                    score += subscore;
+                }
+            }
+            if score < lowest_score && intermediate_state.is_valid() {
+                lowest_score = score;
+                best_field = intermediate_state;
             }
-            let subscore = map.get(&(final_state.sheep, final_state.dog)).unwrap();
-            score = score + subscore;
-        }
-        if score < lowest_score && intermediate_state.is_valid() {
-            lowest_score = score;
-            best_field = intermediate_state;
         }
     }
     best_field
 }
 
-pub(crate) fn run_simulation(map: &HashMap<(Sheep, Dog), f32>) -> f32 {
-    let mut rng = rand::thread_rng();
-    let dog = Dog::at(15, 12);
-    let sheep = Sheep::at(rng.gen_range(0..31), rng.gen_range(0..31));
-    let mut game = Field::with(sheep, dog);
-    while !game.is_valid() {
-        let sheep = Sheep::at(rng.gen_range(0..31), rng.gen_range(0..31));
-        game = Field::with(sheep, dog);
+// a sheep anywhere it can stand but on top of the dog, which would be over before it started
+fn random_start(config: &FieldConfig, rng: &mut impl Rng) -> Field {
+    let dog = config.dog_start;
+    let mut game = Field::with(
+        config,
+        Sheep::at(rng.gen_range(0..config.size), rng.gen_range(0..config.size)),
+        dog,
+    );
+    while !game.is_valid() || game.sheep_won() {
+        let sheep = Sheep::at(rng.gen_range(0..config.size), rng.gen_range(0..config.size));
+        game = Field::with(config, sheep, dog);
     }
-    let expexted_moves = map.get(&(game.sheep, game.dog)).unwrap();
-    let mut actual_moves = 0.0;
-    game.print();
-    while !game.dog_won() && game.is_valid() && !game.sheep_won() {
-        // for _ in 0..5 {
-        let possible_states = game.get_dog_states();
-        let mut best_state = possible_states[0].clone();
-        let mut best_value: &f32 = &10000.0;
-        for possible_state in possible_states {
-            let mut reaction_state = possible_state.clone();
-            reaction_state.move_sheep();
-            let test_value = map
-                .get(&(reaction_state.sheep, reaction_state.dog))
-                .unwrap();
-            if test_value < best_value {
-                best_state = reaction_state;
-                best_value = test_value;
+    game
+}
+
+fn random_pack_start(config: &FieldConfig, pack_size: usize, rng: &mut impl Rng) -> Field {
+    let dogs = config.pack_start(pack_size);
+    loop {
+        let sheep = Sheep::at(rng.gen_range(0..config.size), rng.gen_range(0..config.size));
+        let game = Field::with_pack(config, vec![sheep], dogs.clone());
+        if game.is_valid() && !game.sheep_won() {
+            return game;
+        }
+    }
+}
+
+fn random_flock_start(config: &FieldConfig, flock_size: usize, rng: &mut impl Rng) -> Field {
+    loop {
+        let mut flock: Vec<Sheep> = Vec::new();
+        while flock.len() < flock_size {
+            let sheep = Sheep::at(rng.gen_range(0..config.size), rng.gen_range(0..config.size));
+            let on_dog = sheep.x == config.dog_start.x && sheep.y == config.dog_start.y;
+            if !flock.contains(&sheep) && !on_dog {
+                flock.push(sheep);
             }
         }
-        game = best_state;
-        actual_moves = actual_moves + 1.0;
+        let game = Field::with_flock(config, flock, config.dog_start);
+        if game.is_valid() {
+            return game;
+        }
+    }
+}
+
+// the single sheep utilities of every sheep that still has to be penned, added up
+fn flock_utility(map: &UtilityTable, game: &Field) -> f32 {
+    game.flock
+        .iter()
+        .filter(|sheep| !game.terrain().holds(**sheep, game.dog()))
+        .map(|sheep| map.get((*sheep, game.dog())).unwrap())
+        .sum()
+}
+
+// play a game with the dog moving wherever the policy table says, without looking anything up
+// in the utility map along the way
+pub(crate) fn run_policy_simulation(
+    dog_policy: &PolicyTable,
+    map: &UtilityTable,
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    rng: &mut impl Rng,
+) -> f32 {
+    let mut game = random_start(config, rng);
+    let expexted_moves = map.get((game.sheep(), game.dog())).unwrap();
+    let mut actual_moves = 0.0;
+    while !game.dog_won() && game.is_valid() && !game.sheep_won() {
+        let dog = dog_policy.get((game.sheep(), game.dog())).unwrap();
+        game = game.move_dog_to(0, dog.as_cell());
+        game.move_sheep(policy, rng);
+        actual_moves += 1.0;
     }
     actual_moves - expexted_moves
 }
 
+// play a game against the clock, with the dog following the deadline policy for the moves it has
+// left. true when the sheep is penned in time
+pub(crate) fn run_deadline_simulation(
+    deadline_policy: &DeadlinePolicy,
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    rng: &mut impl Rng,
+) -> bool {
+    let mut game = random_start(config, rng);
+    let mut moves_left = deadline_policy.horizon();
+    while !game.dog_won() && game.is_valid() && !game.sheep_won() && moves_left > 0 {
+        let cell = deadline_policy.next_cell(&game, moves_left);
+        game = game.move_dog_to(0, cell);
+        game.move_sheep(policy, rng);
+        moves_left -= 1;
+    }
+    game.dog_won()
+}
+
 pub(crate) fn run_simulation_with_model(
   model: (f32, f32, f32, f32, f32),
-  map: &HashMap<(Sheep, Dog), f32>,
+  map: &UtilityTable,
   distance_map_sheep: &HashMap<Sheep, f32>,
+  config: &FieldConfig,
+  policy: &dyn SheepPolicy,
+  rng: &mut impl Rng,
 ) -> (f32, bool) {
-  let mut rng = rand::thread_rng();
-  let dog = Dog::at(15, 12);
-  let sheep = Sheep::at(rng.gen_range(0..31), rng.gen_range(0..31));
-  let mut game = Field::with(sheep, dog);
-  while !game.is_valid() {
-      let sheep = Sheep::at(rng.gen_range(0..31), rng.gen_range(0..31));
-      game = Field::with(sheep, dog);
-  }
-  let expexted_moves = map.get(&(game.sheep, game.dog)).unwrap();
+  let mut game = random_start(config, rng);
+  let expexted_moves = map.get((game.sheep(), game.dog())).unwrap();
   let mut actual_moves = 0.0;
   while !game.dog_won() && game.is_valid() && !game.sheep_won() {
       // for _ in 0..5 {
       let possible_states = game.get_dog_states();
       let mut best_state = possible_states[0].clone();
-      let mut best_value: f32 = 10000.0;
+      let mut best_value = f32::MAX;
       for possible_state in possible_states {
           let mut reaction_state = possible_state.clone();
-          reaction_state.move_sheep();
-          let data_point = (reaction_state.sheep, reaction_state.dog);
-          let data_vector = model_2(data_point, &distance_map_sheep);
+          reaction_state.move_sheep(policy, rng);
+          let data_point = (reaction_state.sheep(), reaction_state.dog());
+          let data_vector = model_2(data_point, distance_map_sheep, config);
           let test_value = dot_product(data_vector, model);
           if test_value < best_value {
               best_state = reaction_state;
@@ -118,11 +165,135 @@ pub(crate) fn run_simulation_with_model(
           }
       }
       game = best_state;
-      actual_moves = actual_moves + 1.0;
+      actual_moves += 1.0;
       if actual_moves > 250.0 {
         return (251.0, false);
       }
   }
   game.print();
   (actual_moves - expexted_moves, game.dog_won())
-}
\ No newline at end of file
+}
+
+// herd a whole flock, greedily steering by the single sheep utility map. every sheep has to fit
+// inside the pen, so the default cup, which has room for one, needs swapping for a bigger pen
+// before a flock of two or more can be played
+pub(crate) fn run_flock_simulation(
+    map: &UtilityTable,
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    flock_size: usize,
+    rng: &mut impl Rng,
+) -> (f32, bool) {
+    assert!(
+        flock_size <= config.pen.interior().len(),
+        "the pen only has room for {} sheep",
+        config.pen.interior().len()
+    );
+    let mut game = random_flock_start(config, flock_size, rng);
+    let mut actual_moves = 0.0;
+    while !game.dog_won() && game.is_valid() && !game.sheep_won() {
+        let possible_states = game.get_dog_states();
+        let mut best_state = possible_states[0].clone();
+        let mut best_value = f32::MAX;
+        for possible_state in possible_states {
+            let mut reaction_state = possible_state.clone();
+            reaction_state.move_sheep(policy, rng);
+            let test_value = flock_utility(map, &reaction_state);
+            if test_value < best_value {
+                best_state = reaction_state;
+                best_value = test_value;
+            }
+        }
+        game = best_state;
+        actual_moves += 1.0;
+        if actual_moves > 250.0 {
+            return (251.0, false);
+        }
+    }
+    (actual_moves, game.dog_won())
+}
+
+// play the joint game with a pack of dogs, greedily following the joint utility map. gives up
+// after 250 moves like the other runners
+pub(crate) fn run_pack_simulation(
+    map: &HashMap<PackState, f32>,
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    pack_size: usize,
+    rng: &mut impl Rng,
+) -> (f32, bool) {
+    let mut game = random_pack_start(config, pack_size, rng);
+    let expexted_moves = map.get(&pack_key(&game)).unwrap();
+    let mut actual_moves = 0.0;
+    while !game.dog_won() && game.is_valid() && !game.sheep_won() {
+        let possible_states = game.get_dog_states();
+        let mut best_state = possible_states[0].clone();
+        let mut best_value = f32::MAX;
+        for possible_state in possible_states {
+            let mut reaction_state = possible_state.clone();
+            reaction_state.move_sheep(policy, rng);
+            let test_value = *map.get(&pack_key(&reaction_state)).unwrap();
+            if test_value < best_value {
+                best_state = reaction_state;
+                best_value = test_value;
+            }
+        }
+        game = best_state;
+        actual_moves += 1.0;
+        if actual_moves > 250.0 {
+            return (251.0, false);
+        }
+    }
+    (actual_moves - expexted_moves, game.dog_won())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::data::partition_data;
+    use crate::make_distance_map_sheep;
+    use crate::mdp::SolverOptions;
+    use crate::sheep_policy::Flee;
+    use crate::solve_markov::generate_dog_policy;
+    use crate::solve_markov::generate_optimal_utlility;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    // everything random is drawn from the generator handed in, so the same seed plays the same
+    // games and splits the data the same way
+    #[test]
+    fn the_same_seed_gives_the_same_results() {
+        let config = FieldConfig::new(7);
+        let policy = Flee::default();
+        let options = SolverOptions::default();
+        let (map, _) = generate_optimal_utlility(&config, &policy, &options);
+        let table = generate_dog_policy(&config, &policy, &map, &options);
+        let model = (0.5948616, 0.62768173, 0.07846236, 0.4726258, 0.0911483);
+        let distances = make_distance_map_sheep(&config);
+        let run = |seed: u64| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let games: Vec<f32> = (0..20)
+                .map(|_| run_policy_simulation(&table, &map, &config, &policy, &mut rng))
+                .collect();
+            let model_games: Vec<(f32, bool)> = (0..5)
+                .map(|_| {
+                    run_simulation_with_model(model, &map, &distances, &config, &policy, &mut rng)
+                })
+                .collect();
+            let partition = partition_data(&map, &config, &mut rng);
+            (games, model_games, partition)
+        };
+        assert_eq!(run(7), run(7));
+    }
+
+    #[test]
+    #[should_panic(expected = "room for 1 sheep")]
+    fn a_flock_needs_room_in_the_pen() {
+        let config = FieldConfig::new(7);
+        let map = UtilityTable::new(config.size);
+        let mut rng = StdRng::seed_from_u64(0);
+        run_flock_simulation(&map, &config, &Flee::default(), 2, &mut rng);
+    }
+}
@@ -1,130 +1,780 @@
-
-use crate::field::Entity;
-use crate::field::Cell;
-use crate::field::Field;
 use crate::field::Dog;
+use crate::field::Field;
+use crate::field::FieldConfig;
 use crate::field::Sheep;
+use crate::mdp::best_action;
+use crate::mdp::q_values;
+use crate::mdp::value_iteration;
+use crate::mdp::ConvergenceReport;
+use crate::mdp::Mdp;
+use crate::mdp::Opponent;
+use crate::mdp::SolverOptions;
+use crate::policy_table::PolicyTable;
+use crate::reachability::analyze;
+use crate::reachability::forced_times;
+use crate::reachability::Restricted;
+use crate::sheep_policy::SheepPolicy;
+use crate::terrain::Terrain;
+use crate::utility_table::canonical;
+use crate::utility_table::UtilityTable;
 
 use std::collections::HashMap;
+use std::sync::Arc;
+
+// a state of the game with a pack of dogs; the dogs are kept sorted since they are interchangeable
+pub(crate) type PackState = (Sheep, Vec<Dog>);
+
+// a state of the game with a flock; the sheep keep their order, since they move in that order
+pub(crate) type FlockState = (Vec<Sheep>, Dog);
+
+pub(crate) fn pack_key(field: &Field) -> PackState {
+    let mut dogs = field.dogs.clone();
+    dogs.sort();
+    (field.sheep(), dogs)
+}
+
+// every cell of the field, top to bottom and left to right
+fn cells(config: &FieldConfig) -> Vec<(i32, i32)> {
+    let mut cells = Vec::new();
+    for y in 0..config.size {
+        for x in 0..config.size {
+            cells.push((x, y));
+        }
+    }
+    cells
+}
+
+// what the dog is charged when the sheep runs into it: far more than any herd takes, so losing
+// is never worth it
+const SHEEP_WINS_COST: f32 = 10000.0;
+
+// every game charges one per dog move
+const MOVE_COST: f32 = 1.0;
+
+// what the rest of a finished game costs the dog, nothing once every sheep is penned and
+// SHEEP_WINS_COST once one runs into a dog. None while the game is still going
+pub(crate) fn end_of_game(field: &Field) -> Option<f32> {
+    if field.dog_won() {
+        Some(0.0)
+    } else if field.sheep_won() {
+        Some(SHEEP_WINS_COST)
+    } else {
+        None
+    }
+}
+
+// what the seed guess charges for a sheep: its distance to the goal in sheep moves, + 2
+fn sheep_guess(config: &FieldConfig, sheep: Sheep) -> f32 {
+    let goal = config.pen.goal;
+    config.sheep_moves.distance(sheep.x - goal.0, sheep.y - goal.1) as f32 + 2.0
+}
+
+// one of the dog's moves as the solver weighs it: the move's cost plus the discounted expected
+// utility of where the sheep's reaction leaves the game, and that reaction's distribution
+#[derive(Clone, Debug)]
+pub struct DogMoveValue {
+    pub dog: Dog,
+    pub expected_cost: f32,
+    pub reactions: Vec<(Sheep, f32)>,
+}
+
+// the original game: one dog herding one sheep. the dog picks its next cell, then the sheep reacts.
+// when both the terrain and the sheep policy are the same reflected left to right, only the
+// canonical half of the states is solved and the other half is read off its mirror image. the move
+// sets are always left right symmetric. a mirrored backup adds up the same terms as the full one in
+// a different order, so the two solves agree to float rounding, within a millionth of each
+// utility, rather than bit for bit
+pub struct HerdingGame<'a> {
+    terrain: Arc<Terrain>,
+    policy: &'a dyn SheepPolicy,
+    mirrored: bool,
+}
+
+impl<'a> HerdingGame<'a> {
+    pub fn new(config: &FieldConfig, policy: &'a dyn SheepPolicy) -> Self {
+        Self::on(&Arc::new(Terrain::new(config)), policy)
+    }
 
-// make first value distance to the top + 2
-// t_star(hashmap) -> hashmap
-// calculate the expected moves for a given field
-pub fn t_star(old_utility_map: HashMap<(Sheep, Dog), f32>) -> HashMap<(Sheep, Dog), f32> {
-    let mut new_utility_map = old_utility_map.clone();
-    let beta = 0.99;
-
-    for ((sheep, dog), _value) in &old_utility_map {
-        let test_sheep = Sheep::at(0, 0);
-        let test_dog = Dog::at(1, 0);
-        let state = Field::with(*sheep, *dog);
-        let mut flag = false;
-        if !state.is_valid() || state.sheep_won() || state.dog_won() {
-            continue;
-        }
-        if *dog == test_dog && *sheep == test_sheep {
-            flag = true;
-        }
-        let dog_actions = state.get_dog_states();
-        let mut minimum = f32::MAX;
-        for dog_action in dog_actions {
-            let sheep_actions = dog_action.get_sheep_states();
-            let movement_probability = 1.0 / sheep_actions.len() as f32;
-            let mut summation = 0.0;
-            for sheep_action in sheep_actions {
-                match old_utility_map.get(&(sheep_action.sheep, sheep_action.dog)) {
-                    Some(e) => (),
-                    None => sheep_action.print(),
-                };
-                let action_reward = movement_probability
-                    * old_utility_map
-                        .get(&(sheep_action.sheep, sheep_action.dog))
-                        .unwrap();
-
-                summation = summation + action_reward;
+    // the game played on a terrain that already exists, like a field's
+    pub fn on(terrain: &Arc<Terrain>, policy: &'a dyn SheepPolicy) -> Self {
+        Self {
+            mirrored: terrain.is_mirror_symmetric() && policy.is_mirror_symmetric(),
+            terrain: Arc::clone(terrain),
+            policy,
+        }
+    }
+
+    fn field(&self, state: &(Sheep, Dog)) -> Field {
+        Field::on(&self.terrain, vec![state.0], vec![state.1])
+    }
+
+    // every valid state, mirror images included
+    fn all_states(&self) -> Vec<(Sheep, Dog)> {
+        let cells = cells(&self.terrain.config);
+        let mut states = Vec::new();
+        for (sheep_x, sheep_y) in cells.iter() {
+            for (dog_x, dog_y) in cells.iter() {
+                let state = (Sheep::at(*sheep_x, *sheep_y), Dog::at(*dog_x, *dog_y));
+                if self.field(&state).is_valid() {
+                    states.push(state);
+                }
             }
-            if summation < minimum {
-                minimum = summation;
+        }
+        states
+    }
+
+    // the state the solver keeps the value of
+    fn solved_state(&self, state: (Sheep, Dog)) -> (Sheep, Dog) {
+        if self.mirrored {
+            canonical(self.terrain.size(), state)
+        } else {
+            state
+        }
+    }
+
+    // where games begin: the dog at its starting cell and the sheep anywhere it can stand
+    pub fn starts(&self) -> Vec<(Sheep, Dog)> {
+        let mut starts = Vec::new();
+        for (x, y) in cells(&self.terrain.config) {
+            let state = self.solved_state((Sheep::at(x, y), self.terrain.config.dog_start));
+            if self.field(&state).is_valid() && !starts.contains(&state) {
+                starts.push(state);
             }
         }
-        if flag {
-            println!("minimum for them next to each other: {}", minimum);
+        starts
+    }
+
+    // where the sheep can end up after the dog takes the action, as they are on the field rather
+    // than as the solver stores them
+    fn reactions(&self, state: &(Sheep, Dog), action: &Dog) -> Vec<((Sheep, Dog), f32)> {
+        self.field(&(state.0, *action))
+            .sheep_transitions(0, self.policy)
+            .into_iter()
+            .map(|(field, probability)| ((field.sheep(), field.dog()), probability))
+            .collect()
+    }
+
+    // every move the dog has in the state, with what it is expected to cost from there on. a game
+    // that is over has none
+    pub fn dog_move_values(
+        &self,
+        state: &(Sheep, Dog),
+        map: &UtilityTable,
+        options: &SolverOptions,
+    ) -> Vec<DogMoveValue> {
+        if self.is_terminal(state) {
+            return Vec::new();
+        }
+        q_values(self, map, state, options)
+            .into_iter()
+            .map(|(dog, expected_cost)| DogMoveValue {
+                dog,
+                expected_cost,
+                reactions: self
+                    .reactions(state, &dog)
+                    .into_iter()
+                    .map(|((sheep, _), probability)| (sheep, probability))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    // the dog's best move in every non terminal state of a solved utility map
+    pub fn policy_table(&self, map: &UtilityTable, options: &SolverOptions) -> PolicyTable {
+        let mut table = PolicyTable::new(self.terrain.size());
+        for state in self.all_states() {
+            if let Some(dog) = best_action(self, map, &state, options) {
+                table.set(state, dog);
+            }
+        }
+        table
+    }
+}
+
+impl Mdp for HerdingGame<'_> {
+    type State = (Sheep, Dog);
+    type Action = Dog;
+    type Values = UtilityTable;
+
+    fn states(&self) -> Vec<(Sheep, Dog)> {
+        let mut states = self.all_states();
+        states.retain(|state| self.solved_state(*state) == *state);
+        states
+    }
+
+    fn empty_values(&self) -> UtilityTable {
+        if self.mirrored {
+            UtilityTable::mirrored(self.terrain.size())
+        } else {
+            UtilityTable::new(self.terrain.size())
+        }
+    }
+
+    fn actions(&self, state: &(Sheep, Dog)) -> Vec<Dog> {
+        self.field(state)
+            .get_dog_states()
+            .iter()
+            .map(|field| field.dog())
+            .collect()
+    }
+
+    // reactions that are mirror images of each other are one state to the solver, so each solved
+    // state is listed once with their chances added up
+    fn transitions(&self, state: &(Sheep, Dog), action: &Dog) -> Vec<((Sheep, Dog), f32)> {
+        let mut outcomes = Vec::<((Sheep, Dog), f32)>::new();
+        for (next_state, probability) in self.reactions(state, action) {
+            let next_state = self.solved_state(next_state);
+            match outcomes.iter_mut().find(|(outcome, _)| *outcome == next_state) {
+                Some((_, total)) => *total += probability,
+                None => outcomes.push((next_state, probability)),
+            }
         }
-        new_utility_map.insert((*sheep, *dog), 1.0 + beta * minimum);
+        outcomes
+    }
+
+    fn cost(&self, _state: &(Sheep, Dog), _action: &Dog) -> f32 {
+        MOVE_COST
+    }
+
+    fn is_terminal(&self, state: &(Sheep, Dog)) -> bool {
+        end_of_game(&self.field(state)).is_some()
+    }
+
+    fn is_goal(&self, state: &(Sheep, Dog)) -> bool {
+        self.field(state).dog_won()
+    }
+
+    fn terminal_value(&self, state: &(Sheep, Dog)) -> f32 {
+        end_of_game(&self.field(state)).unwrap_or(SHEEP_WINS_COST)
     }
 
-    new_utility_map
+    fn initial_value(&self, state: &(Sheep, Dog)) -> f32 {
+        sheep_guess(&self.terrain.config, state.0)
+    }
 }
 
-pub(crate) fn generate_optimal_utlility() -> HashMap<(Sheep, Dog), f32> {
-    let mut utility_map = HashMap::new();
+// one sheep against a pack of dogs that move jointly
+pub struct PackGame<'a> {
+    terrain: Arc<Terrain>,
+    policy: &'a dyn SheepPolicy,
+    pack_size: usize,
+}
 
-    let test_field = Field::new();
+impl<'a> PackGame<'a> {
+    pub fn new(config: &FieldConfig, policy: &'a dyn SheepPolicy, pack_size: usize) -> Self {
+        Self {
+            terrain: Arc::new(Terrain::new(config)),
+            policy,
+            pack_size,
+        }
+    }
 
-    for sheep_cell in test_field
-        .grid
-        .clone()
-        .into_iter()
-        .flatten()
-        .collect::<Vec<Cell>>()
-    {
-        let mut new_sheep_cell = sheep_cell.clone();
-        new_sheep_cell.entity = Entity::Sheep;
-        let intermediate_state = test_field.move_sheep_to(new_sheep_cell);
-        for dog_cell in intermediate_state
-            .grid
-            .clone()
+    fn field(&self, state: &PackState) -> Field {
+        Field::on(&self.terrain, vec![state.0], state.1.clone())
+    }
+
+    // every way to place the pack on distinct cells, in sorted order
+    fn placements(&self) -> Vec<Vec<Dog>> {
+        let cells: Vec<Dog> = cells(&self.terrain.config)
             .into_iter()
-            .flatten()
-            .collect::<Vec<Cell>>()
-        {
-            let mut new_dog_cell = dog_cell.clone();
-            new_dog_cell.entity = Entity::Dog;
-            let final_state = intermediate_state.move_dog_to(new_dog_cell);
-            if !final_state.is_valid() {
-                continue;
+            .map(|(x, y)| Dog::at(x, y))
+            .collect();
+        let mut placements: Vec<Vec<Dog>> = vec![vec![]];
+        for _ in 0..self.pack_size {
+            let mut next_placements = Vec::new();
+            for placement in placements {
+                for dog in cells.iter() {
+                    if placement.last().is_none_or(|last| last < dog) {
+                        let mut next_placement = placement.clone();
+                        next_placement.push(*dog);
+                        next_placements.push(next_placement);
+                    }
+                }
             }
-            let sheep_pos = (final_state.sheep.x, final_state.sheep.y);
-            let goal_pos = (
-                final_state.grid.len() as i32 / 2,
-                final_state.grid.len() as i32 / 2,
-            );
-            if final_state.dog_won() {
-                utility_map.insert((final_state.sheep, final_state.dog), 0.0);
-                continue;
+            placements = next_placements;
+        }
+        placements
+    }
+}
+
+impl Mdp for PackGame<'_> {
+    type State = PackState;
+    type Action = Vec<Dog>;
+    type Values = HashMap<PackState, f32>;
+
+    fn states(&self) -> Vec<PackState> {
+        let mut states = Vec::new();
+        for dogs in self.placements() {
+            for (x, y) in cells(&self.terrain.config) {
+                let state = (Sheep::at(x, y), dogs.clone());
+                if self.field(&state).is_valid() {
+                    states.push(state);
+                }
             }
+        }
+        states
+    }
 
-            if final_state.sheep_won() {
-                utility_map.insert((final_state.sheep, final_state.dog), 10000.0);
-                continue;
+    fn empty_values(&self) -> HashMap<PackState, f32> {
+        HashMap::new()
+    }
+
+    fn actions(&self, state: &PackState) -> Vec<Vec<Dog>> {
+        self.field(state)
+            .get_dog_states()
+            .into_iter()
+            .map(|field| field.dogs)
+            .collect()
+    }
+
+    fn transitions(&self, state: &PackState, action: &Vec<Dog>) -> Vec<(PackState, f32)> {
+        self.field(&(state.0, action.clone()))
+            .sheep_transitions(0, self.policy)
+            .into_iter()
+            .map(|(field, probability)| (pack_key(&field), probability))
+            .collect()
+    }
+
+    fn cost(&self, _state: &PackState, _action: &Vec<Dog>) -> f32 {
+        MOVE_COST
+    }
+
+    fn is_terminal(&self, state: &PackState) -> bool {
+        end_of_game(&self.field(state)).is_some()
+    }
+
+    fn is_goal(&self, state: &PackState) -> bool {
+        self.field(state).dog_won()
+    }
+
+    fn terminal_value(&self, state: &PackState) -> f32 {
+        end_of_game(&self.field(state)).unwrap_or(SHEEP_WINS_COST)
+    }
+
+    fn initial_value(&self, state: &PackState) -> f32 {
+        sheep_guess(&self.terrain.config, state.0)
+    }
+}
+
+// one dog herding a whole flock, which reacts one sheep at a time
+pub struct FlockGame<'a> {
+    terrain: Arc<Terrain>,
+    policy: &'a dyn SheepPolicy,
+    flock_size: usize,
+}
+
+impl<'a> FlockGame<'a> {
+    pub fn new(config: &FieldConfig, policy: &'a dyn SheepPolicy, flock_size: usize) -> Self {
+        Self {
+            terrain: Arc::new(Terrain::new(config)),
+            policy,
+            flock_size,
+        }
+    }
+
+    fn field(&self, state: &FlockState) -> Field {
+        Field::on(&self.terrain, state.0.clone(), vec![state.1])
+    }
+
+    // every way to place the flock on distinct cells, in any order
+    fn placements(&self) -> Vec<Vec<Sheep>> {
+        let cells: Vec<Sheep> = cells(&self.terrain.config)
+            .into_iter()
+            .map(|(x, y)| Sheep::at(x, y))
+            .collect();
+        let mut placements: Vec<Vec<Sheep>> = vec![vec![]];
+        for _ in 0..self.flock_size {
+            let mut next_placements = Vec::new();
+            for placement in placements {
+                for sheep in cells.iter() {
+                    if !placement.contains(sheep) {
+                        let mut next_placement = placement.clone();
+                        next_placement.push(*sheep);
+                        next_placements.push(next_placement);
+                    }
+                }
             }
-            let sheep_goal_distance: f32 = (sheep_pos.0 - goal_pos.0).abs() as f32
-                + (sheep_pos.1 - goal_pos.1).abs() as f32
-                + 2.0;
-            let initial_score = sheep_goal_distance;
-            utility_map.insert((final_state.sheep, final_state.dog), initial_score);
+            placements = next_placements;
         }
+        placements
     }
+}
 
-    println!("{:?}", utility_map.len());
+impl Mdp for FlockGame<'_> {
+    type State = FlockState;
+    type Action = Dog;
+    type Values = HashMap<FlockState, f32>;
 
-    loop {
-        let updated_map = t_star(utility_map.clone());
-        let update_size = update_size(&utility_map, &updated_map);
-        println!("update size: {}", update_size);
-        if update_size < 0.01 {
-            return updated_map;
+    fn states(&self) -> Vec<FlockState> {
+        let mut states = Vec::new();
+        for flock in self.placements() {
+            for (x, y) in cells(&self.terrain.config) {
+                let state = (flock.clone(), Dog::at(x, y));
+                if self.field(&state).is_valid() {
+                    states.push(state);
+                }
+            }
         }
-        utility_map = updated_map;
+        states
     }
+
+    fn empty_values(&self) -> HashMap<FlockState, f32> {
+        HashMap::new()
+    }
+
+    fn actions(&self, state: &FlockState) -> Vec<Dog> {
+        self.field(state)
+            .get_dog_states()
+            .iter()
+            .map(|field| field.dog())
+            .collect()
+    }
+
+    fn transitions(&self, state: &FlockState, action: &Dog) -> Vec<(FlockState, f32)> {
+        let mut outcomes = vec![(self.field(&(state.0.clone(), *action)), 1.0)];
+        for index in 0..self.flock_size {
+            let mut next_outcomes = Vec::new();
+            for (field, probability) in outcomes {
+                for (next_field, movement_probability) in field.sheep_transitions(index, self.policy) {
+                    next_outcomes.push((next_field, probability * movement_probability));
+                }
+            }
+            outcomes = next_outcomes;
+        }
+        outcomes
+            .into_iter()
+            .map(|(field, probability)| ((field.flock.clone(), field.dog()), probability))
+            .collect()
+    }
+
+    fn cost(&self, _state: &FlockState, _action: &Dog) -> f32 {
+        MOVE_COST
+    }
+
+    fn is_terminal(&self, state: &FlockState) -> bool {
+        end_of_game(&self.field(state)).is_some()
+    }
+
+    fn is_goal(&self, state: &FlockState) -> bool {
+        self.field(state).dog_won()
+    }
+
+    fn terminal_value(&self, state: &FlockState) -> f32 {
+        end_of_game(&self.field(state)).unwrap_or(SHEEP_WINS_COST)
+    }
+
+    fn initial_value(&self, state: &FlockState) -> f32 {
+        state
+            .0
+            .iter()
+            .map(|sheep| sheep_guess(&self.terrain.config, *sheep))
+            .sum()
+    }
+}
+
+// the utilities along with how the solve went, which says whether max_iterations ran out first
+pub(crate) fn generate_optimal_utlility(
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    options: &SolverOptions,
+) -> (UtilityTable, ConvergenceReport) {
+    value_iteration(&HerdingGame::new(config, policy), options)
+}
+
+// what each of the dog's moves on a one sheep, one dog field is worth according to a utility map
+pub(crate) fn dog_move_values(
+    field: &Field,
+    map: &UtilityTable,
+    policy: &dyn SheepPolicy,
+    options: &SolverOptions,
+) -> Vec<DogMoveValue> {
+    assert!(
+        field.flock.len() == 1 && field.dogs.len() == 1,
+        "move values are for one sheep and one dog, this field has {} and {}",
+        field.flock.len(),
+        field.dogs.len()
+    );
+    assert!(field.is_valid(), "the sheep and the dog have to stand on open cells");
+    assert!(
+        map.size() == field.terrain().size(),
+        "the utility map is for a {}x{} field, not {}x{}",
+        map.size(),
+        map.size(),
+        field.terrain().size(),
+        field.terrain().size()
+    );
+    HerdingGame::on(field.terrain(), policy).dog_move_values(
+        &(field.sheep(), field.dog()),
+        map,
+        options,
+    )
+}
+
+// the same solve over only the states a game from the usual dog start can get to. every other
+// state is left without a utility
+pub(crate) fn generate_reachable_utility(
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    options: &SolverOptions,
+) -> (UtilityTable, ConvergenceReport) {
+    let game = HerdingGame::new(config, policy);
+    let analysis = analyze(&game, &game.starts());
+    value_iteration(&Restricted::new(&game, analysis.reachable), options)
+}
+
+// the utilities against a sheep that always takes whichever of its moves is worst for the dog,
+// so they are worst case discounted herding times
+pub(crate) fn generate_adversarial_utility(
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    options: &SolverOptions,
+) -> (UtilityTable, ConvergenceReport) {
+    let options = SolverOptions {
+        opponent: Opponent::Adversarial,
+        ..options.clone()
+    };
+    value_iteration(&HerdingGame::new(config, policy), &options)
+}
+
+// how many moves the dog needs to pen the sheep however it reacts, infinite where the sheep can
+// always get away
+pub(crate) fn generate_forced_times(config: &FieldConfig, policy: &dyn SheepPolicy) -> UtilityTable {
+    forced_times(&HerdingGame::new(config, policy))
 }
 
-fn update_size(old_map: &HashMap<(Sheep, Dog), f32>, new_map: &HashMap<(Sheep, Dog), f32>) -> f32 {
-  let mut summation: f32 = 0.0;
-  for (key, value) in old_map {
-      let new_val = new_map.get(&key).unwrap();
-      let difference = value - new_val;
-      summation = summation + difference.abs();
-  }
-  2.0 * summation / (1.0 - 0.99)
+pub(crate) fn generate_dog_policy(
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    map: &UtilityTable,
+    options: &SolverOptions,
+) -> PolicyTable {
+    HerdingGame::new(config, policy).policy_table(map, options)
+}
+
+// value iteration over the joint game; the state space grows with the power of the pack size,
+// so this is only meant for small fields
+pub(crate) fn generate_pack_utility(
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    pack_size: usize,
+    options: &SolverOptions,
+) -> (HashMap<PackState, f32>, ConvergenceReport) {
+    value_iteration(&PackGame::new(config, policy, pack_size), options)
+}
+
+// the same for a flock, which grows even faster since the sheep are told apart by their order
+pub(crate) fn generate_flock_utility(
+    config: &FieldConfig,
+    policy: &dyn SheepPolicy,
+    flock_size: usize,
+    options: &SolverOptions,
+) -> (HashMap<FlockState, f32>, ConvergenceReport) {
+    value_iteration(&FlockGame::new(config, policy, flock_size), options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::field::Cell;
+    use crate::mdp::ValueTable;
+    use crate::sheep_policy::Flee;
+    use crate::sheep_policy::RandomWalk;
+
+    // flees like Flee but doesn't claim to be symmetric, so the game keeps every state
+    struct Unmarked(Flee);
+
+    impl SheepPolicy for Unmarked {
+        fn next_cells(&self, field: &Field, index: usize) -> Vec<(Cell, f32)> {
+            self.0.next_cells(field, index)
+        }
+
+        fn name(&self) -> String {
+            format!("unmarked {}", self.0.name())
+        }
+    }
+
+    #[test]
+    fn mirrored_solve_matches_the_full_solve() {
+        let config = FieldConfig::new(7);
+        let options = SolverOptions::default();
+        let policy = Flee::default();
+        let mirrored = HerdingGame::new(&config, &policy);
+        let unmarked = Unmarked(Flee::default());
+        let full = HerdingGame::new(&config, &unmarked);
+        assert!(mirrored.mirrored && !full.mirrored);
+
+        let (mirrored_map, mirrored_report) = value_iteration(&mirrored, &options);
+        let (full_map, full_report) = value_iteration(&full, &options);
+        assert_eq!(mirrored_report.iterations, full_report.iterations);
+        for state in full.states() {
+            let (one, two) = (mirrored_map.value(&state), full_map.value(&state));
+            assert!(
+                (one - two).abs() <= 1e-6 * two.abs().max(1.0),
+                "{:?}: {} mirrored, {} in full",
+                state,
+                one,
+                two
+            );
+        }
+    }
+
+    #[test]
+    fn reachable_solve_matches_the_full_solve() {
+        let config = FieldConfig::new(7);
+        let policy = Flee::default();
+        let options = SolverOptions {
+            tolerance: 1e-3,
+            ..SolverOptions::default()
+        };
+        let (full, _) = generate_optimal_utlility(&config, &policy, &options);
+        let (reachable, report) = generate_reachable_utility(&config, &policy, &options);
+        assert!(report.converged);
+        let mut compared = 0;
+        for (state, value) in reachable.iter() {
+            let full_value = full.get(state).unwrap();
+            assert!(
+                (value - full_value).abs() <= 2.0 * options.tolerance,
+                "{:?}: {} reachable, {} in full",
+                state,
+                value,
+                full_value
+            );
+            compared += 1;
+        }
+        assert!(compared > 0 && compared < full.len());
+    }
+
+    // nothing in the pack game knows about mirroring, so this catches joint moves that depend on
+    // the order of the dogs. a wandering sheep, since Flee breaks ties between dogs to the left
+    #[test]
+    fn the_pack_solve_is_mirror_symmetric() {
+        let config = FieldConfig::new(5);
+        let policy = RandomWalk;
+        let options = SolverOptions {
+            discount: 0.9,
+            ..SolverOptions::default()
+        };
+        let (map, _) = generate_pack_utility(&config, &policy, 2, &options);
+        let mirror = |x: i32| config.size - 1 - x;
+        for ((sheep, dogs), value) in map.iter() {
+            let mut mirrored: Vec<Dog> = dogs
+                .iter()
+                .map(|dog| Dog::at(mirror(dog.x), dog.y))
+                .collect();
+            mirrored.sort();
+            let other = map[&(Sheep::at(mirror(sheep.x), sheep.y), mirrored)];
+            assert!(
+                (value - other).abs() <= 1e-5 * value.abs().max(1.0),
+                "{:?}: {}, mirrored {}",
+                (sheep, dogs),
+                value,
+                other
+            );
+        }
+    }
+
+    // the cheapest move is worth the state's utility, to within the residual the solve stopped
+    // at, and is the move the policy table picks
+    #[test]
+    fn the_cheapest_move_value_is_the_utility() {
+        let config = FieldConfig::new(7);
+        let policy = Flee::default();
+        let options = SolverOptions::default();
+        let (map, _) = generate_optimal_utlility(&config, &policy, &options);
+        let table = generate_dog_policy(&config, &policy, &map, &options);
+        let terrain = Arc::new(Terrain::new(&config));
+        for ((sheep, dog), utility) in map.iter() {
+            let field = Field::on(&terrain, vec![sheep], vec![dog]);
+            if end_of_game(&field).is_some() {
+                assert!(dog_move_values(&field, &map, &policy, &options).is_empty());
+                continue;
+            }
+            let moves = dog_move_values(&field, &map, &policy, &options);
+            let mut cheapest = &moves[0];
+            for dog_move in moves.iter() {
+                if dog_move.expected_cost < cheapest.expected_cost {
+                    cheapest = dog_move;
+                }
+            }
+            assert!(
+                (cheapest.expected_cost - utility).abs() <= options.tolerance,
+                "{:?}: {} against {}",
+                (sheep, dog),
+                cheapest.expected_cost,
+                utility
+            );
+            assert_eq!(Some(cheapest.dog), table.get((sheep, dog)));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "for a 9x9 field")]
+    fn move_values_need_a_map_for_the_field() {
+        let policy = Flee::default();
+        let map = UtilityTable::new(9);
+        let field = Field::new(&FieldConfig::new(7));
+        dog_move_values(&field, &map, &policy, &SolverOptions::default());
+    }
+
+    // the worst outcome of a move costs at least as much as the expected one, so a sheep that
+    // picks it can only make every state dearer
+    #[test]
+    fn adversarial_values_are_never_below_random_ones() {
+        let config = FieldConfig::new(7);
+        let policy = Flee::default();
+        let options = SolverOptions::default();
+        let (random, _) = generate_optimal_utlility(&config, &policy, &options);
+        let (adversarial, _) = generate_adversarial_utility(&config, &policy, &options);
+        for (state, value) in random.iter() {
+            let worst = adversarial.get(state).unwrap();
+            assert!(
+                worst >= value - 2.0 * options.tolerance,
+                "{:?}: {} against {}",
+                state,
+                worst,
+                value
+            );
+        }
+    }
+
+    // a sheep on the middle column fleeing straight down the middle can step left or right, and
+    // the two are the same state once mirrored
+    #[test]
+    fn mirrored_outcomes_are_merged() {
+        let policy = Flee::default();
+        let game = HerdingGame::new(&FieldConfig::new(11), &policy);
+        let outcomes = game.transitions(&(Sheep::at(5, 0), Dog::at(5, 2)), &Dog::at(5, 3));
+        let merged = (Sheep::at(4, 0), Dog::at(5, 3));
+        let probability = outcomes
+            .iter()
+            .find(|(outcome, _)| *outcome == merged)
+            .map(|(_, probability)| *probability);
+        assert_eq!(outcomes.len(), 2, "{:?}", outcomes);
+        assert!(
+            (probability.unwrap() - 2.0 / 3.0).abs() < 1e-6,
+            "{:?}",
+            outcomes
+        );
+    }
+
+    #[test]
+    fn transitions_list_each_solved_state_once() {
+        let policy = Flee::default();
+        let game = HerdingGame::new(&FieldConfig::new(7), &policy);
+        for state in game.states() {
+            if game.is_terminal(&state) {
+                continue;
+            }
+            for action in game.actions(&state) {
+                let outcomes = game.transitions(&state, &action);
+                for (index, (outcome, _)) in outcomes.iter().enumerate() {
+                    assert_eq!(game.solved_state(*outcome), *outcome);
+                    assert!(outcomes[..index].iter().all(|(other, _)| other != outcome));
+                }
+                let total: f32 = outcomes.iter().map(|(_, probability)| probability).sum();
+                assert!((total - 1.0).abs() < 1e-6);
+            }
+        }
+    }
 }
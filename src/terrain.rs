@@ -0,0 +1,169 @@
+use crate::field::Dog;
+use crate::field::Entity;
+use crate::field::FieldConfig;
+use crate::field::Sheep;
+
+// a game state as the solver sees it: just where the sheep and the dog stand
+pub type State = (Sheep, Dog);
+
+// where a state sits when every (sheep, dog) pair on a size x size field is packed into one
+// vector, sheep row by row and then dog row by row, or None when either animal is off the field
+pub(crate) fn state_index(size: i32, state: State) -> Option<usize> {
+    let (sheep, dog) = state;
+    let on_field = |x: i32, y: i32| x >= 0 && y >= 0 && x < size && y < size;
+    if !on_field(sheep.x, sheep.y) || !on_field(dog.x, dog.y) {
+        return None;
+    }
+    let area = (size * size) as usize;
+    let sheep_offset = (sheep.y * size + sheep.x) as usize;
+    let dog_offset = (dog.y * size + dog.x) as usize;
+    Some(sheep_offset * area + dog_offset)
+}
+
+pub(crate) fn state_at(size: i32, index: usize) -> State {
+    let size = size as usize;
+    let area = size * size;
+    let (sheep, dog) = (index / area, index % area);
+    (
+        Sheep::at((sheep % size) as i32, (sheep / size) as i32),
+        Dog::at((dog % size) as i32, (dog / size) as i32),
+    )
+}
+
+// the part of a field that never changes during a game. fences, obstacles and the pen interior
+// are looked up in flat tables, so fields only carry positions and share one terrain between them
+#[derive(Debug, PartialEq, Eq)]
+pub struct Terrain {
+    pub config: FieldConfig,
+    cells: Vec<Entity>,
+    penned: Vec<bool>,
+}
+
+impl Terrain {
+    pub fn new(config: &FieldConfig) -> Self {
+        let area = (config.size * config.size) as usize;
+        let mut terrain = Self {
+            config: config.clone(),
+            cells: vec![Entity::Empty; area],
+            penned: vec![false; area],
+        };
+        for (x, y) in config.pen.fences() {
+            let offset = terrain.offset(x, y);
+            terrain.cells[offset] = Entity::Fence;
+        }
+        for (x, y) in config.obstacles.iter() {
+            let offset = terrain.offset(*x, *y);
+            terrain.cells[offset] = Entity::Obstacle;
+        }
+        for (x, y) in config.pen.interior() {
+            if config.contains(x, y) {
+                let offset = terrain.offset(x, y);
+                terrain.penned[offset] = true;
+            }
+        }
+        terrain
+    }
+
+    pub fn size(&self) -> i32 {
+        self.config.size
+    }
+
+    fn offset(&self, x: i32, y: i32) -> usize {
+        (y * self.config.size + x) as usize
+    }
+
+    // what stands on the cell before any animal is placed, or None off the field
+    pub fn entity(&self, x: i32, y: i32) -> Option<Entity> {
+        if self.config.contains(x, y) {
+            Some(self.cells[self.offset(x, y)])
+        } else {
+            None
+        }
+    }
+
+    // on the field and free of fences and obstacles
+    pub fn is_open(&self, x: i32, y: i32) -> bool {
+        self.entity(x, y) == Some(Entity::Empty)
+    }
+
+    // in the pen's interior
+    pub fn encloses(&self, x: i32, y: i32) -> bool {
+        self.config.contains(x, y) && self.penned[self.offset(x, y)]
+    }
+
+    // Pen::holds with the interior looked up in the table
+    pub fn holds(&self, sheep: Sheep, dog: Dog) -> bool {
+        self.config
+            .pen
+            .holds(sheep, dog, |x, y| self.encloses(x, y))
+    }
+
+    // whether the game looks the same reflected left to right: every fence, obstacle and pen cell
+    // has a twin across the middle column, and the goal and the entrance sit on that column
+    pub fn is_mirror_symmetric(&self) -> bool {
+        let size = self.config.size;
+        let on_axis = |(x, _): (i32, i32)| x == size - 1 - x;
+        if !on_axis(self.config.pen.goal) || !on_axis(self.config.pen.entrance()) {
+            return false;
+        }
+        (0..size).all(|y| {
+            (0..size).all(|x| {
+                let (mirrored, here) = (self.offset(size - 1 - x, y), self.offset(x, y));
+                self.cells[here] == self.cells[mirrored]
+                    && self.penned[here] == self.penned[mirrored]
+            })
+        })
+    }
+
+    // every (sheep, dog) pair on the field, packed into 0..state_count
+    pub fn state_count(&self) -> usize {
+        let area = (self.config.size * self.config.size) as usize;
+        area * area
+    }
+
+    pub fn index(&self, state: State) -> usize {
+        state_index(self.config.size, state)
+            .unwrap_or_else(|| panic!("{:?} is off a {}x{} field", state, self.size(), self.size()))
+    }
+
+    pub fn state(&self, index: usize) -> State {
+        state_at(self.config.size, index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::pen::Pen;
+    use crate::pen::PenShape;
+    use crate::pen::Side;
+
+    // the cached table answers the pen's rule for every pair of cells
+    #[test]
+    fn holds_follows_the_pen() {
+        let pens = [
+            Pen::centered(9),
+            Pen::new((2, 6), PenShape::Cup { width: 2, depth: 2 }, Side::Right),
+            Pen {
+                goal: (6, 2),
+                ..Pen::new((6, 3), PenShape::Cup { width: 3, depth: 1 }, Side::Down)
+            },
+        ];
+        for pen in pens {
+            let terrain = Terrain::new(&FieldConfig::new(9).with_pen(pen.clone()));
+            let interior = pen.interior();
+            let cells: Vec<(i32, i32)> = (0..81).map(|cell| (cell % 9, cell / 9)).collect();
+            for (x, y) in cells.iter() {
+                assert_eq!(terrain.encloses(*x, *y), interior.contains(&(*x, *y)));
+                for (dog_x, dog_y) in cells.iter() {
+                    let (sheep, dog) = (Sheep::at(*x, *y), Dog::at(*dog_x, *dog_y));
+                    assert_eq!(
+                        terrain.holds(sheep, dog),
+                        pen.holds(sheep, dog, |x, y| interior.contains(&(x, y)))
+                    );
+                }
+            }
+        }
+    }
+}
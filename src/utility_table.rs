@@ -0,0 +1,219 @@
+use crate::field::Dog;
+use crate::field::Sheep;
+use crate::mdp::ValueTable;
+use crate::terrain::state_at;
+use crate::terrain::state_index;
+
+// the state reflected left to right across the middle column
+pub(crate) fn mirror(size: i32, state: (Sheep, Dog)) -> (Sheep, Dog) {
+    let (sheep, dog) = state;
+    (
+        Sheep::at(size - 1 - sheep.x, sheep.y),
+        Dog::at(size - 1 - dog.x, dog.y),
+    )
+}
+
+// which of a state and its mirror image stands for both: the one with the sheep on the left half,
+// or with the dog on the left half when the sheep is on the middle column
+pub(crate) fn canonical(size: i32, state: (Sheep, Dog)) -> (Sheep, Dog) {
+    let (sheep, dog) = state;
+    let sheep_side = 2 * sheep.x - (size - 1);
+    let dog_side = 2 * dog.x - (size - 1);
+    if sheep_side > 0 || (sheep_side == 0 && dog_side > 0) {
+        mirror(size, state)
+    } else {
+        state
+    }
+}
+
+// the utility of every (sheep, dog) state in one flat vector, laid out by state_index, so
+// looking a state up is arithmetic instead of hashing. states without a utility, like a dog on a
+// fence, hold the INVALID sentinel. a mirrored table only has room for the canonical states and
+// reflects everything else onto them, for games that look the same left to right
+#[derive(Clone, Debug)]
+pub struct UtilityTable {
+    size: i32,
+    mirrored: bool,
+    values: Vec<f32>,
+    // how many states have a utility, kept up to date by set so len doesn't walk the table
+    valid: usize,
+}
+
+impl UtilityTable {
+    pub const INVALID: f32 = f32::NAN;
+
+    // every state starts out invalid
+    pub fn new(size: i32) -> Self {
+        let area = (size * size) as usize;
+        Self {
+            size,
+            mirrored: false,
+            values: vec![Self::INVALID; area * area],
+            valid: 0,
+        }
+    }
+
+    // only the sheep cells up to the middle column are stored
+    pub fn mirrored(size: i32) -> Self {
+        let area = (size * size) as usize;
+        let half = (size * ((size + 1) / 2)) as usize;
+        Self {
+            size,
+            mirrored: true,
+            values: vec![Self::INVALID; half * area],
+            valid: 0,
+        }
+    }
+
+    // where a state's utility is kept, or None off the field
+    fn slot(&self, state: (Sheep, Dog)) -> Option<usize> {
+        let index = state_index(self.size, state)?;
+        if !self.mirrored {
+            return Some(index);
+        }
+        let (sheep, dog) = canonical(self.size, state);
+        let area = (self.size * self.size) as usize;
+        let sheep_offset = (sheep.y * ((self.size + 1) / 2) + sheep.x) as usize;
+        let dog_offset = (dog.y * self.size + dog.x) as usize;
+        Some(sheep_offset * area + dog_offset)
+    }
+
+    pub fn size(&self) -> i32 {
+        self.size
+    }
+
+    // None for invalid states and anything off the field
+    pub fn get(&self, state: (Sheep, Dog)) -> Option<f32> {
+        self.slot(state)
+            .map(|slot| self.values[slot])
+            .filter(|value| !value.is_nan())
+    }
+
+    pub fn set(&mut self, state: (Sheep, Dog), value: f32) {
+        let slot = self
+            .slot(state)
+            .unwrap_or_else(|| panic!("{:?} is off a {}x{} table", state, self.size, self.size));
+        // a slot of a mirrored table stands for a state and its reflection, unless the two are
+        // the same state
+        let states = if self.mirrored && mirror(self.size, state) != state {
+            2
+        } else {
+            1
+        };
+        match (self.values[slot].is_nan(), value.is_nan()) {
+            (true, false) => self.valid += states,
+            (false, true) => self.valid -= states,
+            _ => (),
+        }
+        self.values[slot] = value;
+    }
+
+    pub fn contains(&self, state: (Sheep, Dog)) -> bool {
+        self.get(state).is_some()
+    }
+
+    // the valid states with their utilities, sheep row by row and then dog row by row. a mirrored
+    // table lists the reflected states too
+    pub fn iter(&self) -> impl Iterator<Item = ((Sheep, Dog), f32)> + '_ {
+        let area = (self.size * self.size) as usize;
+        (0..area * area).filter_map(move |index| {
+            let state = state_at(self.size, index);
+            self.get(state).map(|value| (state, value))
+        })
+    }
+
+    // how many valid states there are
+    pub fn len(&self) -> usize {
+        self.valid
+    }
+}
+
+// two tables match when they hold the same states with the same utilities, mirrored or not
+impl PartialEq for UtilityTable {
+    fn eq(&self, other: &Self) -> bool {
+        self.size == other.size && self.iter().eq(other.iter())
+    }
+}
+
+impl ValueTable<(Sheep, Dog)> for UtilityTable {
+    fn value(&self, state: &(Sheep, Dog)) -> f32 {
+        self.get(*state)
+            .unwrap_or_else(|| panic!("no utility for {:?}", state))
+    }
+
+    fn set_value(&mut self, state: &(Sheep, Dog), value: f32) {
+        self.set(*state, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_then_get() {
+        let mut table = UtilityTable::new(7);
+        let state = (Sheep::at(1, 2), Dog::at(4, 0));
+        assert_eq!(table.get(state), None);
+        assert_eq!(table.len(), 0);
+        table.set(state, 3.5);
+        assert_eq!(table.get(state), Some(3.5));
+        assert_eq!(table.get((Sheep::at(4, 0), Dog::at(1, 2))), None);
+        assert_eq!(table.get((Sheep::at(7, 0), Dog::at(1, 2))), None);
+        assert_eq!(table.len(), 1);
+        table.set(state, 2.0);
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "off a 7x7 table")]
+    fn setting_a_state_off_the_field_panics() {
+        UtilityTable::new(7).set((Sheep::at(-1, 0), Dog::at(0, 0)), 1.0);
+    }
+
+    #[test]
+    fn the_invalid_sentinel_removes_a_state() {
+        let mut table = UtilityTable::new(7);
+        let state = (Sheep::at(1, 2), Dog::at(4, 0));
+        table.set(state, 3.5);
+        table.set(state, UtilityTable::INVALID);
+        assert!(!table.contains(state));
+        assert_eq!(table.len(), 0);
+        assert_eq!(table.iter().count(), 0);
+    }
+
+    // a mirrored table answers for a state and its reflection from one slot
+    #[test]
+    fn a_mirrored_table_reflects_states() {
+        let mut table = UtilityTable::mirrored(7);
+        let state = (Sheep::at(5, 2), Dog::at(2, 0));
+        table.set(state, 3.5);
+        assert_eq!(table.get(mirror(7, state)), Some(3.5));
+        assert_eq!(table.len(), 2);
+        let middle = (Sheep::at(3, 1), Dog::at(3, 5));
+        table.set(middle, 1.0);
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.iter().count(), table.len());
+        table.set(mirror(7, state), UtilityTable::INVALID);
+        assert!(!table.contains(state));
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn tables_match_on_their_states_and_utilities() {
+        let state = (Sheep::at(5, 2), Dog::at(2, 0));
+        let mut mirrored = UtilityTable::mirrored(7);
+        mirrored.set(state, 3.5);
+        let mut full = UtilityTable::new(7);
+        full.set(state, 3.5);
+        assert!(mirrored != full);
+        full.set(mirror(7, state), 3.5);
+        assert!(mirrored == full);
+        full.set(state, 3.0);
+        assert!(mirrored != full);
+        let mut larger = UtilityTable::new(9);
+        larger.set(state, 3.5);
+        larger.set(mirror(7, state), 3.5);
+        assert!(mirrored != larger);
+    }
+}